@@ -1,17 +1,185 @@
+use crate::model::Model;
+use crate::savestate::{StateReader, StateWriter};
+use crate::wav::WavWriter;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// One frame is 70224 CPU cycles: 154 lines of 456 cycles each. At the
+// 4194304 Hz clock that makes about 59.73 frames per second.
+pub const CYCLES_PER_FRAME: u32 = 70224;
+pub const CPU_CLOCK: u32 = 4194304;
+
 pub struct Audio {
-    // Fields for audio handling
+    // Current output level of each of the four APU channels. The channels
+    // aren't emulated yet, so these stay silent until they are.
+    channel_outputs: [i16; 4],
+    // The output capacitors act as a high-pass filter that removes DC offset.
+    // The CGB's charges faster, so it sounds noticeably thinner.
+    capacitors: [f64; 2], // Left, right
+    charge_factor: f64,
+    samples: Vec<i16>, // Interleaved stereo samples generated this frame
+    // Cycles run but not yet turned into a whole sample, times SAMPLE_RATE.
+    // A frame is about 738.4 samples, so the fraction carries over.
+    sample_remainder: u32,
+    // Set when not running in real time. Sped up or slowed down output would
+    // play at the wrong pitch, so it is replaced by silence instead.
+    pub muted: bool,
+    recorder: Option<AudioRecorder>,
+}
+
+// Writes the mixed stereo output (and optionally each channel on its own) to WAV files
+pub struct AudioRecorder {
+    mixed: WavWriter,
+    channels: Option<Vec<WavWriter>>,
+}
+
+impl AudioRecorder {
+    // `path` receives the mixed output. With `per_channel` set, channel N is
+    // written next to it as `<stem>_chN.wav`.
+    pub fn create(path: &str, per_channel: bool) -> std::io::Result<Self> {
+        let mixed = WavWriter::create(path, SAMPLE_RATE, 2)?;
+        let channels = if per_channel {
+            let stem = path.strip_suffix(".wav").unwrap_or(path);
+            let mut writers = Vec::with_capacity(4);
+            for channel in 1..=4 {
+                let channel_path = format!("{}_ch{}.wav", stem, channel);
+                writers.push(WavWriter::create(&channel_path, SAMPLE_RATE, 1)?);
+            }
+            Some(writers)
+        } else {
+            None
+        };
+
+        Ok(Self { mixed, channels })
+    }
+
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.mixed.finish()?;
+        if let Some(channels) = &mut self.channels {
+            for writer in channels {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Audio {
-    pub fn new() -> Self {
-        println!("Initializing audio");
+    pub fn new(model: Model) -> Self {
+        log::debug!(target: "apu", "Initializing audio");
+
+        // Per-clock charge factors, scaled to one step per output sample
+        let clock_factor: f64 = if model.is_cgb() { 0.998943 } else { 0.999958 };
+        let charge_factor = clock_factor.powf(CPU_CLOCK as f64 / SAMPLE_RATE as f64);
+
         Self {
-            // Initialize fields for audio
+            channel_outputs: [0; 4],
+            capacitors: [0.0; 2],
+            charge_factor,
+            samples: Vec::new(),
+            sample_remainder: 0,
+            muted: false,
+            recorder: None,
+        }
+    }
+
+    // Generate the samples for `cycles` CPU cycles, normally a frame's worth,
+    // by mixing the channel outputs
+    pub fn update(&mut self, cycles: u32) {
+        let elapsed = self.sample_remainder as u64 + cycles as u64 * SAMPLE_RATE as u64;
+        let count = (elapsed / CPU_CLOCK as u64) as usize;
+        self.sample_remainder = (elapsed % CPU_CLOCK as u64) as u32;
+
+        self.samples.clear();
+        let mut channel_samples: [Vec<i16>; 4] = std::array::from_fn(|_| Vec::with_capacity(count));
+        for _ in 0..count {
+            // Keep the filters running while muted so unmuting doesn't pop
+            let (left, right) = self.mix();
+            let gate = if self.muted { 0 } else { 1 };
+            self.samples.push(left * gate);
+            self.samples.push(right * gate);
+            for (channel, samples) in channel_samples.iter_mut().enumerate() {
+                samples.push(self.channel_outputs[channel] * gate);
+            }
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            let mut result = recorder.mixed.write_samples(&self.samples);
+            if let Some(channels) = &mut recorder.channels {
+                for (writer, samples) in channels.iter_mut().zip(channel_samples.iter()) {
+                    result = result.and_then(|_| writer.write_samples(samples));
+                }
+            }
+            if let Err(e) = result {
+                log::error!(target: "apu", "Audio recording failed, stopping: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    // Interleaved stereo samples at SAMPLE_RATE from the last `update`
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    // Mix the four channels into a stereo pair. Every channel is panned to
+    // both sides until NR51 panning is emulated.
+    fn mix(&mut self) -> (i16, i16) {
+        let sum: i32 = self.channel_outputs.iter().map(|&s| s as i32).sum();
+        let mixed = sum as f64 / 4.0;
+        let left = self.high_pass(mixed, 0);
+        let right = self.high_pass(mixed, 1);
+        (left as i16, right as i16)
+    }
+
+    fn high_pass(&mut self, input: f64, side: usize) -> f64 {
+        let output = input - self.capacitors[side];
+        self.capacitors[side] = input - output * self.charge_factor;
+        output
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        for output in self.channel_outputs {
+            w.write_u16(output as u16);
+        }
+        for capacitor in self.capacitors {
+            w.write_f64(capacitor);
+        }
+        w.write_u32(self.sample_remainder);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for output in self.channel_outputs.iter_mut() {
+            *output = r.read_u16()? as i16;
+        }
+        for capacitor in self.capacitors.iter_mut() {
+            *capacitor = r.read_f64()?;
+        }
+        self.sample_remainder = r.read_u32()?;
+        Ok(())
+    }
+
+    // Record everything `update` generates from now on. Until the channels
+    // are emulated that's silence, but it's still a frame-accurate capture
+    // for comparing runs.
+    pub fn start_recording(&mut self, path: &str, per_channel: bool) -> std::io::Result<()> {
+        self.stop_recording();
+        self.recorder = Some(AudioRecorder::create(path, per_channel)?);
+        log::info!(target: "apu", "Recording audio to {}", path);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(mut recorder) = self.recorder.take() {
+            if let Err(e) = recorder.finish() {
+                log::error!(target: "apu", "Failed to finish audio recording: {}", e);
+            } else {
+                log::info!(target: "apu", "Audio recording stopped");
+            }
         }
     }
 
-    pub fn update(&mut self) {
-        // Placeholder for audio updates
-        // println!("Updating audio");
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
     }
 }
@@ -0,0 +1,64 @@
+use crate::error::RomLoadError;
+
+// Fields of the cartridge header at 0x0100-0x014F that the emulator cares about
+pub struct Header {
+    pub title: String,
+    pub cgb_flag: u8,          // 0x143: 0x80 = CGB enhanced, 0xC0 = CGB only
+    pub old_licensee: u8,      // 0x14B
+    pub new_licensee: [u8; 2], // 0x144-0x145, only used when old_licensee is 0x33
+    pub header_checksum: u8,   // 0x14D
+    title_bytes: [u8; 16],     // 0x134-0x143, raw
+}
+
+impl Header {
+    // Parse the header from a ROM image (or the start of memory it is loaded into)
+    pub fn parse(rom: &[u8]) -> Self {
+        let byte = |addr: usize| rom.get(addr).copied().unwrap_or(0);
+
+        let mut title_bytes = [0u8; 16];
+        for (i, b) in title_bytes.iter_mut().enumerate() {
+            *b = byte(0x134 + i);
+        }
+
+        // The title shrank to 11 bytes once the CGB flag and manufacturer code moved in
+        let title_len = if byte(0x143) & 0x80 != 0 { 11 } else { 16 };
+        let title: String = title_bytes[..title_len]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| b as char)
+            .collect();
+
+        Self {
+            title: title.trim().to_string(),
+            cgb_flag: byte(0x143),
+            old_licensee: byte(0x14B),
+            new_licensee: [byte(0x144), byte(0x145)],
+            header_checksum: byte(0x14D),
+            title_bytes,
+        }
+    }
+
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 != 0
+    }
+
+    pub fn nintendo_licensed(&self) -> bool {
+        self.old_licensee == 0x01 || (self.old_licensee == 0x33 && &self.new_licensee == b"01")
+    }
+
+    // Sum of the 16 title bytes. The CGB boot ROM uses it to pick a
+    // compatibility palette for Nintendo-published monochrome games.
+    pub fn title_checksum(&self) -> u8 {
+        self.title_bytes
+            .iter()
+            .fold(0u8, |sum, &b| sum.wrapping_add(b))
+    }
+}
+
+// Read a ROM image from a file
+pub fn read_rom(path: &str) -> Result<Vec<u8>, RomLoadError> {
+    std::fs::read(path).map_err(|error| RomLoadError::Read {
+        path: path.to_string(),
+        error,
+    })
+}
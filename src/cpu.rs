@@ -1,25 +1,84 @@
+use crate::cartridge::Header;
+use crate::debug::Breakpoints;
+use crate::disasm::length;
+use crate::error::EmuError;
+use crate::model::Model;
+use crate::savestate::{StateReader, StateWriter};
+use crate::trace::Trace;
+
+// Clock cycles taken by each unprefixed opcode. Conditional jumps, calls and
+// returns are counted as not taken. The illegal opcodes count as 4.
+#[rustfmt::skip]
+const OPCODE_CYCLES: [u8; 256] = [
+    4, 12,  8,  8,  4,  4,  8,  4, 20,  8,  8,  8,  4,  4,  8,  4, // 0x00
+    4, 12,  8,  8,  4,  4,  8,  4, 12,  8,  8,  8,  4,  4,  8,  4, // 0x10
+    8, 12,  8,  8,  4,  4,  8,  4,  8,  8,  8,  8,  4,  4,  8,  4, // 0x20
+    8, 12,  8,  8, 12, 12, 12,  4,  8,  8,  8,  8,  4,  4,  8,  4, // 0x30
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 0x40
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 0x50
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 0x60
+    8,  8,  8,  8,  8,  8,  4,  8,  4,  4,  4,  4,  4,  4,  8,  4, // 0x70
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 0x80
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 0x90
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 0xA0
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 0xB0
+    8, 12, 12, 16, 12, 16,  8, 16,  8, 16, 12,  4, 12, 24,  8, 16, // 0xC0
+    8, 12, 12,  4, 12, 16,  8, 16,  8, 16, 12,  4, 12,  4,  8, 16, // 0xD0
+   12, 12,  8,  4,  4, 16,  8, 16, 16,  4, 16,  4,  4,  4,  8, 16, // 0xE0
+   12, 12,  8,  4,  4, 16,  8, 16, 12,  8, 16,  4,  4,  4,  8, 16, // 0xF0
+];
+
 pub struct CPU {
     pub registers: Registers,
-    pub interrupts_enabled: bool, // New field to track interrupts
+    pub interrupts_enabled: bool, // IME, set by EI and RETI
+    pub locked: bool,             // Hung by an illegal opcode
+    pub model: Model,
+    pub breakpoints: Breakpoints, // Not part of save states
+    pub trace: Trace,             // Not part of save states
 }
 
 impl CPU {
-    pub fn new() -> Self {
+    pub fn new(model: Model) -> Self {
         Self {
             registers: Registers::new(),
-            interrupts_enabled: true, // Start with interrupts enabled by default
+            interrupts_enabled: false, // Off at power-on until the game runs EI
+            locked: false,
+            model,
+            breakpoints: Breakpoints::default(),
+            trace: Trace::default(),
         }
     }
 
-    pub fn step(&mut self, mmu: &mut crate::mmu::MMU) {
+    // Jump straight to the cartridge with the registers the boot ROM would have left
+    pub fn skip_boot_rom(&mut self, header: &Header) {
+        self.registers = Registers::post_boot(self.model, header);
+        // The boot ROM never enables interrupts
+        self.interrupts_enabled = false;
+    }
+
+    // Execute one instruction and return how many clock cycles it took. If
+    // the instruction is on a breakpoint nothing is executed and it takes 0.
+    // An instruction that can't be executed is reported as an error, and
+    // `error_cycles` says how long it took.
+    pub fn step(&mut self, mmu: &mut crate::mmu::MMU) -> Result<u32, EmuError> {
+        // A locked up CPU does nothing, but the rest of the machine runs on
+        if self.locked {
+            return Ok(4);
+        }
         let pc = self.registers.pc;
+        let opcode = mmu.peek_byte(pc);
+        if self.breakpoints.should_break(pc, opcode, mmu.bank_at(pc)) {
+            return Ok(0);
+        }
+        self.trace.record(&self.registers, mmu);
         let opcode = mmu.read_byte(pc);
+        let cycles = Self::instruction_cycles(opcode, mmu.peek_byte(pc.wrapping_add(1)));
 
         // Log the current opcode and PC
         // println!("PC: 0x{:04X}, Opcode: 0x{:02X}", pc, opcode);
 
         // Execute the opcode
-        self.decode_and_execute(opcode, mmu);
+        self.decode_and_execute(opcode, mmu)?;
 
         // // Log the state of the registers after execution
         // println!(
@@ -31,9 +90,72 @@ impl CPU {
         //     self.registers.hl(),
         //     self.registers.sp,
         // );
+
+        Ok(cycles)
+    }
+
+    // The cycles an instruction that couldn't be executed takes anyway: a
+    // skipped one its usual time, and a lock-up 4 like every step after it
+    pub fn error_cycles(error: &EmuError) -> u32 {
+        match *error {
+            EmuError::IllegalOpcode { .. } => 4,
+            EmuError::UnimplementedOpcode { opcode, .. } => Self::instruction_cycles(opcode, 0),
+            EmuError::UnimplementedCbOpcode { opcode, .. } => {
+                Self::instruction_cycles(0xCB, opcode)
+            }
+        }
+    }
+
+    // CB-prefixed opcodes take 8 cycles, or 16 when they work on (HL). BIT
+    // only reads (HL), so it takes 12.
+    fn instruction_cycles(opcode: u8, next_byte: u8) -> u32 {
+        if opcode != 0xCB {
+            return OPCODE_CYCLES[opcode as usize] as u32;
+        }
+        match next_byte {
+            0x40..=0x7F if next_byte & 0x07 == 6 => 12,
+            _ if next_byte & 0x07 == 6 => 16,
+            _ => 8,
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        let r = &self.registers;
+        for value in [r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l] {
+            w.write_u8(value);
+        }
+        w.write_u16(r.sp);
+        w.write_u16(r.pc);
+        w.write_bool(self.interrupts_enabled);
+        w.write_bool(self.locked);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let registers = &mut self.registers;
+        for register in [
+            &mut registers.a,
+            &mut registers.f,
+            &mut registers.b,
+            &mut registers.c,
+            &mut registers.d,
+            &mut registers.e,
+            &mut registers.h,
+            &mut registers.l,
+        ] {
+            *register = r.read_u8()?;
+        }
+        registers.sp = r.read_u16()?;
+        registers.pc = r.read_u16()?;
+        self.interrupts_enabled = r.read_bool()?;
+        self.locked = r.read_bool()?;
+        Ok(())
     }
 
-    fn decode_and_execute(&mut self, opcode: u8, mmu: &mut crate::mmu::MMU) {
+    fn decode_and_execute(
+        &mut self,
+        opcode: u8,
+        mmu: &mut crate::mmu::MMU,
+    ) -> Result<(), EmuError> {
         match opcode {
             0x00 => {
                 // NOP - No operation
@@ -229,9 +351,11 @@ impl CPU {
                 let low = mmu.read_byte(self.registers.sp) as u16;
                 let high = mmu.read_byte(self.registers.sp + 1) as u16;
                 let addr = (high << 8) | low;
-                println!(
+                log::trace!(
+                    target: "cpu",
                     "RET - Returning to 0x{:04X} from stack address 0x{:04X}",
-                    addr, self.registers.sp
+                    addr,
+                    self.registers.sp
                 );
                 self.registers.sp += 2;
                 self.registers.pc = addr;
@@ -240,9 +364,11 @@ impl CPU {
                 // LD (HL-), A - Store A into memory at HL, then decrement HL
                 let addr = self.registers.hl();
                 mmu.write_byte(addr, self.registers.a);
-                println!(
+                log::trace!(
+                    target: "cpu",
                     "LD (HL-), A - Writing A (0x{:02X}) to address 0x{:04X}",
-                    self.registers.a, addr
+                    self.registers.a,
+                    addr
                 );
                 self.registers.set_hl(addr.wrapping_sub(1));
                 self.registers.pc += 1;
@@ -318,21 +444,30 @@ impl CPU {
             0xCB => {
                 // Prefix CB instruction - Handle two-byte opcodes
                 let next_opcode = mmu.read_byte(self.registers.pc + 1);
-                self.execute_cb_opcode(next_opcode, mmu);
+                let result = self.execute_cb_opcode(next_opcode, mmu);
                 self.registers.pc += 2;
+                return result;
+            }
+            0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+                // Opcodes the SM83 doesn't have hang it for good
+                self.locked = true;
+                return Err(EmuError::IllegalOpcode {
+                    pc: self.registers.pc,
+                    opcode,
+                });
             }
             _ => {
-                // Handle unknown opcodes
-                println!(
-                    "Unknown opcode: 0x{:02X}, at PC: 0x{:04X}",
-                    opcode, self.registers.pc
-                );
-                self.registers.pc += 1;
+                // Skip unimplemented opcodes along with their operands, so
+                // the next instruction is decoded from where it really starts
+                let pc = self.registers.pc;
+                self.registers.pc = pc.wrapping_add(length(opcode));
+                return Err(EmuError::UnimplementedOpcode { pc, opcode });
             }
         }
+        Ok(())
     }
 
-    fn execute_cb_opcode(&mut self, opcode: u8, mmu: &mut crate::mmu::MMU) {
+    fn execute_cb_opcode(&mut self, opcode: u8, mmu: &mut crate::mmu::MMU) -> Result<(), EmuError> {
         match opcode {
             0x11 => {
                 // Example CB opcode - RL C (Rotate left through carry)
@@ -342,13 +477,13 @@ impl CPU {
                 self.set_flags(self.registers.c == 0, false, false, Some(carry));
             }
             _ => {
-                // Handle unknown CB opcodes
-                println!(
-                    "Unknown CB-prefixed opcode: 0x{:02X}, at PC: 0x{:04X}",
-                    opcode, self.registers.pc
-                );
+                return Err(EmuError::UnimplementedCbOpcode {
+                    pc: self.registers.pc,
+                    opcode,
+                });
             }
         }
+        Ok(())
     }
 
     fn increment_byte(&mut self, value: u8) -> u8 {
@@ -388,6 +523,7 @@ impl CPU {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Registers {
     pub a: u8,
     pub f: u8, // Flags register
@@ -402,18 +538,70 @@ pub struct Registers {
 }
 
 impl Registers {
+    // Power-on state: execution starts at the top of the boot ROM
     pub fn new() -> Self {
-        Self {
-            a: 0,
-            f: 0,
-            b: 0,
-            c: 0,
-            d: 0,
-            e: 0,
-            h: 0,
-            l: 0,
-            sp: 0xFFFE, // Initial stack pointer value
-            pc: 0x100,  // Start after BIOS
+        Self::default()
+    }
+
+    // State left behind by each model's boot ROM when it jumps to the cartridge at 0x100
+    pub fn post_boot(model: Model, header: &Header) -> Self {
+        // DMG and MGB leave H and C set unless the header checksum is zero
+        let dmg_f = if header.header_checksum == 0 {
+            0x80
+        } else {
+            0xB0
+        };
+
+        let (af, bc, de, hl) = match model {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x0100 | dmg_f, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF00 | dmg_f, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Sgb2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+            Model::Cgb | Model::Agb if !header.supports_cgb() => {
+                Self::compatibility_mode_registers(model, header)
+            }
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+            Model::Agb => (0x1100, 0x0100, 0xFF56, 0x000D),
+        };
+
+        let mut registers = Self::new();
+        registers.set_af(af);
+        registers.set_bc(bc);
+        registers.set_de(de);
+        registers.set_hl(hl);
+        registers.sp = 0xFFFE;
+        registers.pc = 0x100; // Start after BIOS
+        registers
+    }
+
+    // AF, BC, DE and HL after the CGB boot ROM hands a monochrome game over in
+    // compatibility mode. B holds the title checksum it used to pick a palette.
+    fn compatibility_mode_registers(model: Model, header: &Header) -> (u16, u16, u16, u16) {
+        let b = if header.nintendo_licensed() {
+            header.title_checksum()
+        } else {
+            0
+        };
+        let hl = if b == 0x43 || b == 0x58 {
+            0x991A
+        } else {
+            0x007C
+        };
+
+        if model == Model::Agb {
+            // The AGB boot ROM finishes with INC B, which also sets Z and H
+            let b = b.wrapping_add(1);
+            let mut f = 0;
+            if b == 0 {
+                f |= 0x80;
+            }
+            if b & 0x0F == 0 {
+                f |= 0x20;
+            }
+            (0x1100 | f, (b as u16) << 8, 0x0008, hl)
+        } else {
+            (0x1180, (b as u16) << 8, 0x0008, hl)
         }
     }
 
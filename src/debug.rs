@@ -0,0 +1,145 @@
+use std::cell::Cell;
+use std::fmt;
+
+use crate::error::EmuError;
+
+// Where execution should stop, checked before each instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    Pc(u16),                         // About to execute this address, in any bank
+    Opcode(u8),                      // About to execute this opcode
+    Banked { bank: u16, addr: u16 }, // About to execute this address with this bank mapped
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Pc(addr) => write!(f, "PC {:04X}", addr),
+            Breakpoint::Opcode(opcode) => write!(f, "opcode {:02X}", opcode),
+            Breakpoint::Banked { bank, addr } => write!(f, "PC {:02X}:{:04X}", bank, addr),
+        }
+    }
+}
+
+// A range of memory to stop on accesses to, checked after each instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16, // Inclusive
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match (self.on_read, self.on_write) {
+            (true, true) => "read/write",
+            (true, false) => "read",
+            _ => "write",
+        };
+        if self.start == self.end {
+            write!(f, "{} {:04X}", access, self.start)
+        } else {
+            write!(f, "{} {:04X}-{:04X}", access, self.start, self.end)
+        }
+    }
+}
+
+// Why the machine stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(Breakpoint),
+    Watchpoint { addr: u16, value: u8, write: bool },
+    Error(EmuError), // The CPU couldn't execute an instruction
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(breakpoint) => write!(f, "Breakpoint at {}", breakpoint),
+            Stop::Watchpoint { addr, value, write } => {
+                let access = if *write { "Write of" } else { "Read of" };
+                write!(f, "{} {:02X} at {:04X}", access, value, addr)
+            }
+            Stop::Error(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+// The CPU's breakpoints. When one is hit the instruction isn't executed, so
+// the machine can be inspected just before it runs.
+#[derive(Default)]
+pub struct Breakpoints {
+    pub list: Vec<Breakpoint>,
+    resuming: bool, // Let the next instruction run even if it's on a breakpoint
+    hit: Option<Breakpoint>,
+}
+
+impl Breakpoints {
+    // Called before executing `opcode` at `pc`; returns true to stop instead
+    pub fn should_break(&mut self, pc: u16, opcode: u8, bank: u16) -> bool {
+        if std::mem::take(&mut self.resuming) || self.list.is_empty() {
+            return false;
+        }
+        self.hit = self
+            .list
+            .iter()
+            .copied()
+            .find(|breakpoint| match *breakpoint {
+                Breakpoint::Pc(addr) => addr == pc,
+                Breakpoint::Opcode(op) => op == opcode,
+                Breakpoint::Banked { bank: b, addr } => addr == pc && b == bank,
+            });
+        self.hit.is_some()
+    }
+
+    // Step over whatever breakpoint the machine is stopped on
+    pub fn resume(&mut self) {
+        self.resuming = true;
+    }
+
+    pub fn take_hit(&mut self) -> Option<Breakpoint> {
+        self.hit.take()
+    }
+
+    pub fn is_hit(&self) -> bool {
+        self.hit.is_some()
+    }
+}
+
+// The MMU's watchpoints. Accesses are checked from `read_byte`, which only
+// has shared access to the MMU, so the hit is kept in a Cell. Instruction
+// fetches count as reads.
+#[derive(Default)]
+pub struct Watchpoints {
+    pub list: Vec<Watchpoint>,
+    hit: Cell<Option<Stop>>,
+}
+
+impl Watchpoints {
+    pub fn check(&self, addr: u16, value: u8, write: bool) {
+        if self.list.is_empty() {
+            return;
+        }
+        let watched = self.list.iter().any(|watchpoint| {
+            (watchpoint.start..=watchpoint.end).contains(&addr)
+                && if write {
+                    watchpoint.on_write
+                } else {
+                    watchpoint.on_read
+                }
+        });
+        // Keep the first access an instruction made
+        if watched && self.hit.get().is_none() {
+            self.hit.set(Some(Stop::Watchpoint { addr, value, write }));
+        }
+    }
+
+    pub fn take_hit(&self) -> Option<Stop> {
+        self.hit.take()
+    }
+
+    pub fn is_hit(&self) -> bool {
+        self.hit.get().is_some()
+    }
+}
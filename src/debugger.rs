@@ -0,0 +1,555 @@
+use std::io::Write;
+
+use rustboy::audio::CYCLES_PER_FRAME;
+use rustboy::debug::{Breakpoint, Stop, Watchpoint};
+use rustboy::disasm::disassemble_at;
+use rustboy::error::EmuError;
+use rustboy::gameboy::GameBoy;
+use rustboy::gdb::{GdbResume, GdbStub};
+use rustboy::oam::{LineSprites, Sprite};
+use rustboy::symbols::Symbols;
+
+use crate::listing::format_line;
+use crate::options::Options;
+
+// How long `next` runs a call for before giving up on it returning
+const MAX_NEXT_CYCLES: u64 = 60 * CYCLES_PER_FRAME as u64;
+
+const HELP: &str = "Commands (numbers are hex):
+  step [n]           s  Execute n instructions, 1 by default
+  next               n  Execute one instruction, running calls until they return
+  continue           c  Resume the game
+  break <addr>       b  Stop before executing addr
+  break <bank>:<addr>   Stop before executing addr with bank mapped there
+  break op <opcode>     Stop before executing opcode
+  watch [r|w|rw] <addr>[-<addr>]
+                     w  Stop after an instruction accesses memory, writes by default
+  list               l  List breakpoints and watchpoints
+  delete <n>         d  Remove the nth entry in the list
+  regs               r  Show the registers and flags
+  x <addr> [len]        Dump memory, 0x40 bytes by default
+  dis [addr] [n]        Disassemble n instructions, 10 from PC by default
+  set <reg> <value>     Set a register: a, f, b, c, d, e, h, l, af, bc, de, hl, sp or pc
+  set <addr> <value>    Write a byte to memory
+  oam [line]            List the sprites, marking those drawn or dropped on a line
+  quit               q  Exit the emulator
+Addresses can also be labels from the game's .sym file, with an optional +offset.
+An empty line repeats the last command.";
+
+// What to do once the debugger prompt is left
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    Quit,
+}
+
+// What to do when the CPU can't execute an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    Halt,     // Report it and stop the emulator
+    Debug,    // Report it and stop in the debugger
+    Continue, // Report it and carry on; a locked up CPU stays locked up
+}
+
+impl std::str::FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "halt" => Ok(ErrorPolicy::Halt),
+            "debug" => Ok(ErrorPolicy::Debug),
+            "continue" => Ok(ErrorPolicy::Continue),
+            _ => Err(format!("Unknown error policy: {}", s)),
+        }
+    }
+}
+
+// Whoever takes over when the game stops: GDB if it's attached, otherwise
+// the built-in debugger
+pub struct Debugging {
+    debugger: Debugger,
+    gdb: Option<GdbStub>,
+    start_stopped: bool,
+    on_error: ErrorPolicy,
+}
+
+impl Debugging {
+    // With --gdb, waits for GDB to connect
+    pub fn new(options: &Options) -> Self {
+        let gdb = options.gdb.and_then(|port| {
+            GdbStub::listen(port)
+                .inspect_err(|e| println!("Failed to listen for GDB on port {}: {}", port, e))
+                .ok()
+        });
+        Self {
+            debugger: Debugger::new(),
+            start_stopped: options.debug || gdb.is_some(),
+            gdb,
+            on_error: options.on_error,
+        }
+    }
+
+    // Before the first frame, stop if asked to
+    pub fn start(&mut self, gameboy: &mut GameBoy) -> Resume {
+        if self.start_stopped {
+            self.stop(gameboy, None)
+        } else {
+            Resume::Continue
+        }
+    }
+
+    // After each frame, deal with any error the frame ran into, then stop if
+    // a breakpoint or watchpoint was hit or GDB wants to interrupt
+    pub fn check(&mut self, gameboy: &mut GameBoy, result: Result<(), EmuError>) -> Resume {
+        if let Err(error) = result {
+            match self.on_error {
+                ErrorPolicy::Halt => {
+                    log::error!(target: "cpu", "{}", error);
+                    return Resume::Quit;
+                }
+                ErrorPolicy::Debug => return self.stop(gameboy, Some(Stop::Error(error))),
+                ErrorPolicy::Continue => log::error!(target: "cpu", "{}", error),
+            }
+        }
+
+        let stop = gameboy.take_debug_stop();
+        let interrupted = self.gdb.as_mut().is_some_and(|gdb| gdb.interrupted());
+        if stop.is_some() || interrupted {
+            self.stop(gameboy, stop)
+        } else {
+            Resume::Continue
+        }
+    }
+
+    // Stop because the user asked, from the window
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub fn break_in(&mut self, gameboy: &mut GameBoy) -> Resume {
+        self.stop(gameboy, None)
+    }
+
+    fn stop(&mut self, gameboy: &mut GameBoy, stop: Option<Stop>) -> Resume {
+        let Some(gdb) = &mut self.gdb else {
+            return self.debugger.enter(gameboy, stop);
+        };
+        match gdb.stop(gameboy, stop) {
+            GdbResume::Continue => Resume::Continue,
+            GdbResume::Detach => {
+                println!("GDB detached");
+                self.gdb = None;
+                Resume::Continue
+            }
+            GdbResume::Kill => Resume::Quit,
+        }
+    }
+}
+
+// A command-line debugger that takes over the terminal while the game is
+// stopped. Breakpoints and watchpoints live in the CPU and MMU, so they keep
+// working while the game runs normally.
+struct Debugger {
+    last_command: String,
+}
+
+impl Debugger {
+    fn new() -> Self {
+        Self {
+            last_command: String::new(),
+        }
+    }
+
+    // Show why the game stopped, then take commands until it should resume
+    fn enter(&mut self, gameboy: &mut GameBoy, stop: Option<Stop>) -> Resume {
+        match stop {
+            Some(stop) => println!("{}", stop),
+            None => println!("Stopped in the debugger, type help for commands"),
+        }
+        print_location(gameboy);
+
+        loop {
+            print!("(rustboy) ");
+            let _ = std::io::stdout().flush();
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return Resume::Quit,
+                Ok(_) => {}
+            }
+
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            self.last_command = line.clone();
+
+            match self.execute(gameboy, &line) {
+                Ok(Some(resume)) => return resume,
+                Ok(None) => {}
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    // Run one command, returning how to resume if it leaves the prompt
+    fn execute(&mut self, gameboy: &mut GameBoy, line: &str) -> Result<Option<Resume>, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(None);
+        };
+
+        match command {
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                step(gameboy, count);
+            }
+            "n" | "next" => next(gameboy),
+            "c" | "continue" => {
+                gameboy.cpu.breakpoints.resume();
+                return Ok(Some(Resume::Continue));
+            }
+            "b" | "break" => {
+                let breakpoint = parse_breakpoint(&gameboy.symbols, args)?;
+                println!("Breakpoint at {}", breakpoint);
+                gameboy.cpu.breakpoints.list.push(breakpoint);
+            }
+            "w" | "watch" => {
+                let watchpoint = parse_watchpoint(&gameboy.symbols, args)?;
+                println!("Watchpoint on {}", watchpoint);
+                gameboy.mmu.watchpoints.list.push(watchpoint);
+            }
+            "l" | "list" => list(gameboy),
+            "d" | "delete" => {
+                let n = parse_number(args.first().ok_or("Usage: delete <n>")?)? as usize;
+                let breakpoints = &mut gameboy.cpu.breakpoints.list;
+                let watchpoints = &mut gameboy.mmu.watchpoints.list;
+                // Breakpoints are numbered first, then watchpoints
+                match n.checked_sub(1) {
+                    Some(i) if i < breakpoints.len() => {
+                        breakpoints.remove(i);
+                    }
+                    Some(i) if i - breakpoints.len() < watchpoints.len() => {
+                        watchpoints.remove(i - breakpoints.len());
+                    }
+                    _ => return Err(format!("No entry {:X} in the list", n)),
+                }
+            }
+            "r" | "regs" => print_registers(gameboy),
+            "x" => {
+                let addr = args.first().ok_or("Usage: x <addr> [len]")?;
+                let addr = parse_address(&gameboy.symbols, addr)?;
+                let len = match args.get(1) {
+                    Some(len) => parse_number(len)?,
+                    None => 0x40,
+                };
+                hexdump(gameboy, addr, len);
+            }
+            "dis" => {
+                let addr = match args.first() {
+                    Some(addr) => parse_address(&gameboy.symbols, addr)?,
+                    None => gameboy.cpu.registers.pc,
+                };
+                let count = match args.get(1) {
+                    Some(count) => parse_number(count)?,
+                    None => 10,
+                };
+                disassemble(gameboy, addr, count);
+            }
+            "set" => {
+                let [target, value] = args else {
+                    return Err("Usage: set <reg|addr> <value>".to_string());
+                };
+                set(gameboy, target, parse_number(value)?)?;
+            }
+            "oam" => {
+                let line = match args.first() {
+                    Some(line) => Some(
+                        u8::try_from(parse_number(line)?)
+                            .map_err(|_| format!("No line {} on the screen", line))?,
+                    ),
+                    None => None,
+                };
+                list_sprites(gameboy, line);
+            }
+            "q" | "quit" => return Ok(Some(Resume::Quit)),
+            "h" | "help" => println!("{}", HELP),
+            _ => {
+                return Err(format!(
+                    "Unknown command: {}, type help for a list",
+                    command
+                ))
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Run one instruction, returning why the machine stopped if it did
+fn step_once(gameboy: &mut GameBoy) -> (u32, Option<Stop>) {
+    match gameboy.step() {
+        Ok(cycles) => (cycles, gameboy.take_debug_stop()),
+        Err(e) => (0, Some(Stop::Error(e))),
+    }
+}
+
+// Run up to `count` instructions, stopping early on a breakpoint, watchpoint
+// or error
+fn step(gameboy: &mut GameBoy, count: u16) {
+    gameboy.cpu.breakpoints.resume();
+    for _ in 0..count {
+        if let (_, Some(stop)) = step_once(gameboy) {
+            println!("{}", stop);
+            break;
+        }
+    }
+    print_location(gameboy);
+}
+
+// Step, treating a call as a single instruction
+fn next(gameboy: &mut GameBoy) {
+    let pc = gameboy.cpu.registers.pc;
+    let instruction = disassemble_at(&gameboy.mmu, pc);
+    if !instruction.text.starts_with("CALL") && !instruction.text.starts_with("RST") {
+        step(gameboy, 1);
+        return;
+    }
+
+    let return_address = pc.wrapping_add(instruction.length());
+    let mut cycles = 0;
+    let mut steps = 0;
+    gameboy.cpu.breakpoints.resume();
+    while cycles < MAX_NEXT_CYCLES {
+        let (step_cycles, stop) = step_once(gameboy);
+        cycles += step_cycles as u64;
+        steps += 1;
+        if let Some(stop) = stop {
+            println!("{}", stop);
+            break;
+        }
+        if gameboy.cpu.registers.pc == return_address {
+            break;
+        }
+    }
+    if gameboy.cpu.registers.pc != return_address && cycles >= MAX_NEXT_CYCLES {
+        println!("Call still running after {} instructions", steps);
+    }
+    print_location(gameboy);
+}
+
+fn print_location(gameboy: &GameBoy) {
+    print_instruction(gameboy, gameboy.cpu.registers.pc);
+}
+
+// Disassemble the instruction at `addr` with labels from the symbol file,
+// under its own label if it has one, and return its length
+fn print_instruction(gameboy: &GameBoy, addr: u16) -> u16 {
+    let symbols = &gameboy.symbols;
+    let bank = gameboy.mmu.bank_at(addr);
+    if let Some(label) = symbols.label(bank, addr) {
+        println!("{}:", label);
+    }
+    let instruction = disassemble_at(&gameboy.mmu, addr);
+    let operand = instruction
+        .target
+        .and_then(|target| symbols.label(gameboy.mmu.bank_at(target), target));
+    println!("{}", format_line(bank, addr, &instruction, operand));
+    instruction.length()
+}
+
+fn print_registers(gameboy: &GameBoy) {
+    let r = &gameboy.cpu.registers;
+    let flags: String = [(0x80, 'Z'), (0x40, 'N'), (0x20, 'H'), (0x10, 'C')]
+        .iter()
+        .map(|&(mask, name)| if r.f & mask != 0 { name } else { '-' })
+        .collect();
+    println!(
+        "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} {} IME={}",
+        r.af(),
+        r.bc(),
+        r.de(),
+        r.hl(),
+        r.sp,
+        r.pc,
+        flags,
+        gameboy.cpu.interrupts_enabled as u8
+    );
+}
+
+fn list(gameboy: &GameBoy) {
+    let breakpoints = &gameboy.cpu.breakpoints.list;
+    let watchpoints = &gameboy.mmu.watchpoints.list;
+    if breakpoints.is_empty() && watchpoints.is_empty() {
+        println!("No breakpoints or watchpoints");
+    }
+    for (i, breakpoint) in breakpoints.iter().enumerate() {
+        println!("{:X}: break {}", i + 1, breakpoint);
+    }
+    for (i, watchpoint) in watchpoints.iter().enumerate() {
+        println!("{:X}: watch {}", breakpoints.len() + i + 1, watchpoint);
+    }
+}
+
+fn disassemble(gameboy: &GameBoy, mut addr: u16, count: u16) {
+    for _ in 0..count {
+        addr = addr.wrapping_add(print_instruction(gameboy, addr));
+    }
+}
+
+// 16 bytes to a line, with the printable ones alongside
+fn hexdump(gameboy: &GameBoy, addr: u16, len: u16) {
+    let bytes: Vec<u8> = (0..len)
+        .map(|i| gameboy.mmu.peek_byte(addr.wrapping_add(i)))
+        .collect();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect();
+        let line_addr = addr.wrapping_add(line as u16 * 16);
+        println!("{:04X}: {:<47}  {}", line_addr, hex.join(" "), text);
+    }
+}
+
+// Every OAM entry, with + before those drawn on `line` and ! before those
+// the 10 sprite limit dropped from it
+fn list_sprites(gameboy: &GameBoy, line: Option<u8>) {
+    let mmu = &gameboy.mmu;
+    let on_line = line
+        .map(|line| LineSprites::new(mmu, line))
+        .unwrap_or_default();
+    if let Some(line) = line {
+        println!(
+            "Line {:02X}: {} sprites drawn, {} dropped",
+            line,
+            on_line.drawn.len(),
+            on_line.dropped.len()
+        );
+    }
+    for sprite in Sprite::all(mmu) {
+        let mark = if on_line.drawn.contains(&sprite.number) {
+            '+'
+        } else if on_line.dropped.contains(&sprite.number) {
+            '!'
+        } else {
+            ' '
+        };
+        println!("{} {}", mark, sprite);
+    }
+}
+
+fn set(gameboy: &mut GameBoy, target: &str, value: u16) -> Result<(), String> {
+    let r = &mut gameboy.cpu.registers;
+    let byte = || u8::try_from(value).map_err(|_| format!("{:X} doesn't fit in a byte", value));
+    match target.to_lowercase().as_str() {
+        "a" => r.a = byte()?,
+        "f" => r.f = byte()? & 0xF0,
+        "b" => r.b = byte()?,
+        "c" => r.c = byte()?,
+        "d" => r.d = byte()?,
+        "e" => r.e = byte()?,
+        "h" => r.h = byte()?,
+        "l" => r.l = byte()?,
+        "af" => r.set_af(value),
+        "bc" => r.set_bc(value),
+        "de" => r.set_de(value),
+        "hl" => r.set_hl(value),
+        "sp" => r.sp = value,
+        "pc" => {
+            r.pc = value;
+            // Moving PC frees a CPU locked up by an illegal opcode
+            gameboy.cpu.locked = false;
+        }
+        _ => {
+            let addr = parse_address(&gameboy.symbols, target)?;
+            let value = byte()?;
+            gameboy.mmu.write_byte(addr, value);
+            // Our own write shouldn't trip a watchpoint
+            gameboy.take_debug_stop();
+        }
+    }
+    Ok(())
+}
+
+// "<addr>", "<bank>:<addr>", "<label>" or "op <opcode>"
+fn parse_breakpoint(symbols: &Symbols, args: &[&str]) -> Result<Breakpoint, String> {
+    match args {
+        ["op", opcode] => {
+            let opcode = parse_number(opcode)?;
+            u8::try_from(opcode)
+                .map(Breakpoint::Opcode)
+                .map_err(|_| format!("Invalid opcode: {:X}", opcode))
+        }
+        [location] => match location.split_once(':') {
+            Some((bank, addr)) => Ok(Breakpoint::Banked {
+                bank: parse_number(bank)?,
+                addr: parse_number(addr)?,
+            }),
+            None => match resolve_label(symbols, location) {
+                // Labels in switchable ROM only apply to their own bank
+                Some((bank, addr @ 0x4000..=0x7FFF)) => Ok(Breakpoint::Banked { bank, addr }),
+                Some((_, addr)) => Ok(Breakpoint::Pc(addr)),
+                None => Ok(Breakpoint::Pc(parse_number(location)?)),
+            },
+        },
+        _ => Err("Usage: break <addr> | break <bank>:<addr> | break op <opcode>".to_string()),
+    }
+}
+
+// "[r|w|rw] <addr>[-<addr>]"
+fn parse_watchpoint(symbols: &Symbols, args: &[&str]) -> Result<Watchpoint, String> {
+    let (access, range) = match args {
+        [access, range] => (*access, *range),
+        [range] => ("w", *range),
+        _ => return Err("Usage: watch [r|w|rw] <addr>[-<addr>]".to_string()),
+    };
+    let (on_read, on_write) = match access {
+        "r" => (true, false),
+        "w" => (false, true),
+        "rw" => (true, true),
+        _ => return Err(format!("Unknown access: {}, use r, w or rw", access)),
+    };
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_address(symbols, start)?, parse_address(symbols, end)?),
+        None => {
+            let addr = parse_address(symbols, range)?;
+            (addr, addr)
+        }
+    };
+    if end < start {
+        return Err(format!("Empty range: {}", range));
+    }
+    Ok(Watchpoint {
+        start,
+        end,
+        on_read,
+        on_write,
+    })
+}
+
+// A label, optionally plus a hex offset, or failing that a number
+fn parse_address(symbols: &Symbols, s: &str) -> Result<u16, String> {
+    match resolve_label(symbols, s) {
+        Some((_, addr)) => Ok(addr),
+        None => parse_number(s),
+    }
+}
+
+// "<label>" or "<label>+<offset>" to a bank and address
+fn resolve_label(symbols: &Symbols, s: &str) -> Option<(u16, u16)> {
+    let (label, offset) = match s.split_once('+') {
+        Some((label, offset)) => (label, parse_number(offset).ok()?),
+        None => (s, 0),
+    };
+    let (bank, addr) = symbols.address(label)?;
+    Some((bank, addr.wrapping_add(offset)))
+}
+
+// Hex, with an optional 0x or $ prefix
+fn parse_number(s: &str) -> Result<u16, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid number: {}", s))
+}
@@ -0,0 +1,386 @@
+use crate::mmu::MMU;
+
+// Mnemonics for the unprefixed opcodes. Operands are filled in from the
+// bytes that follow: {d8} and {d16} are immediates, {a8} is an address in
+// 0xFF00-0xFFFF, {a16} an absolute address, {r8} a relative jump and {e8} a
+// signed offset from SP. The CPU relies on these for instruction lengths,
+// so the two can't disagree on where an instruction ends. An empty entry is
+// an opcode the SM83 doesn't have.
+#[rustfmt::skip]
+const MNEMONICS: [&str; 256] = [
+    // 0x00
+    "NOP",
+    "LD BC, {d16}",
+    "LD (BC), A",
+    "INC BC",
+    "INC B",
+    "DEC B",
+    "LD B, {d8}",
+    "RLCA",
+    "LD ({a16}), SP",
+    "ADD HL, BC",
+    "LD A, (BC)",
+    "DEC BC",
+    "INC C",
+    "DEC C",
+    "LD C, {d8}",
+    "RRCA",
+    // 0x10
+    "STOP {d8}",
+    "LD DE, {d16}",
+    "LD (DE), A",
+    "INC DE",
+    "INC D",
+    "DEC D",
+    "LD D, {d8}",
+    "RLA",
+    "JR {r8}",
+    "ADD HL, DE",
+    "LD A, (DE)",
+    "DEC DE",
+    "INC E",
+    "DEC E",
+    "LD E, {d8}",
+    "RRA",
+    // 0x20
+    "JR NZ, {r8}",
+    "LD HL, {d16}",
+    "LD (HL+), A",
+    "INC HL",
+    "INC H",
+    "DEC H",
+    "LD H, {d8}",
+    "DAA",
+    "JR Z, {r8}",
+    "ADD HL, HL",
+    "LD A, (HL+)",
+    "DEC HL",
+    "INC L",
+    "DEC L",
+    "LD L, {d8}",
+    "CPL",
+    // 0x30
+    "JR NC, {r8}",
+    "LD SP, {d16}",
+    "LD (HL-), A",
+    "INC SP",
+    "INC (HL)",
+    "DEC (HL)",
+    "LD (HL), {d8}",
+    "SCF",
+    "JR C, {r8}",
+    "ADD HL, SP",
+    "LD A, (HL-)",
+    "DEC SP",
+    "INC A",
+    "DEC A",
+    "LD A, {d8}",
+    "CCF",
+    // 0x40
+    "LD B, B",
+    "LD B, C",
+    "LD B, D",
+    "LD B, E",
+    "LD B, H",
+    "LD B, L",
+    "LD B, (HL)",
+    "LD B, A",
+    "LD C, B",
+    "LD C, C",
+    "LD C, D",
+    "LD C, E",
+    "LD C, H",
+    "LD C, L",
+    "LD C, (HL)",
+    "LD C, A",
+    // 0x50
+    "LD D, B",
+    "LD D, C",
+    "LD D, D",
+    "LD D, E",
+    "LD D, H",
+    "LD D, L",
+    "LD D, (HL)",
+    "LD D, A",
+    "LD E, B",
+    "LD E, C",
+    "LD E, D",
+    "LD E, E",
+    "LD E, H",
+    "LD E, L",
+    "LD E, (HL)",
+    "LD E, A",
+    // 0x60
+    "LD H, B",
+    "LD H, C",
+    "LD H, D",
+    "LD H, E",
+    "LD H, H",
+    "LD H, L",
+    "LD H, (HL)",
+    "LD H, A",
+    "LD L, B",
+    "LD L, C",
+    "LD L, D",
+    "LD L, E",
+    "LD L, H",
+    "LD L, L",
+    "LD L, (HL)",
+    "LD L, A",
+    // 0x70
+    "LD (HL), B",
+    "LD (HL), C",
+    "LD (HL), D",
+    "LD (HL), E",
+    "LD (HL), H",
+    "LD (HL), L",
+    "HALT",
+    "LD (HL), A",
+    "LD A, B",
+    "LD A, C",
+    "LD A, D",
+    "LD A, E",
+    "LD A, H",
+    "LD A, L",
+    "LD A, (HL)",
+    "LD A, A",
+    // 0x80
+    "ADD A, B",
+    "ADD A, C",
+    "ADD A, D",
+    "ADD A, E",
+    "ADD A, H",
+    "ADD A, L",
+    "ADD A, (HL)",
+    "ADD A, A",
+    "ADC A, B",
+    "ADC A, C",
+    "ADC A, D",
+    "ADC A, E",
+    "ADC A, H",
+    "ADC A, L",
+    "ADC A, (HL)",
+    "ADC A, A",
+    // 0x90
+    "SUB B",
+    "SUB C",
+    "SUB D",
+    "SUB E",
+    "SUB H",
+    "SUB L",
+    "SUB (HL)",
+    "SUB A",
+    "SBC A, B",
+    "SBC A, C",
+    "SBC A, D",
+    "SBC A, E",
+    "SBC A, H",
+    "SBC A, L",
+    "SBC A, (HL)",
+    "SBC A, A",
+    // 0xA0
+    "AND B",
+    "AND C",
+    "AND D",
+    "AND E",
+    "AND H",
+    "AND L",
+    "AND (HL)",
+    "AND A",
+    "XOR B",
+    "XOR C",
+    "XOR D",
+    "XOR E",
+    "XOR H",
+    "XOR L",
+    "XOR (HL)",
+    "XOR A",
+    // 0xB0
+    "OR B",
+    "OR C",
+    "OR D",
+    "OR E",
+    "OR H",
+    "OR L",
+    "OR (HL)",
+    "OR A",
+    "CP B",
+    "CP C",
+    "CP D",
+    "CP E",
+    "CP H",
+    "CP L",
+    "CP (HL)",
+    "CP A",
+    // 0xC0
+    "RET NZ",
+    "POP BC",
+    "JP NZ, {a16}",
+    "JP {a16}",
+    "CALL NZ, {a16}",
+    "PUSH BC",
+    "ADD A, {d8}",
+    "RST $00",
+    "RET Z",
+    "RET",
+    "JP Z, {a16}",
+    "PREFIX CB",
+    "CALL Z, {a16}",
+    "CALL {a16}",
+    "ADC A, {d8}",
+    "RST $08",
+    // 0xD0
+    "RET NC",
+    "POP DE",
+    "JP NC, {a16}",
+    "",
+    "CALL NC, {a16}",
+    "PUSH DE",
+    "SUB {d8}",
+    "RST $10",
+    "RET C",
+    "RETI",
+    "JP C, {a16}",
+    "",
+    "CALL C, {a16}",
+    "",
+    "SBC A, {d8}",
+    "RST $18",
+    // 0xE0
+    "LDH ({a8}), A",
+    "POP HL",
+    "LDH (C), A",
+    "",
+    "",
+    "PUSH HL",
+    "AND {d8}",
+    "RST $20",
+    "ADD SP, {e8}",
+    "JP HL",
+    "LD ({a16}), A",
+    "",
+    "",
+    "",
+    "XOR {d8}",
+    "RST $28",
+    // 0xF0
+    "LDH A, ({a8})",
+    "POP AF",
+    "LDH A, (C)",
+    "DI",
+    "",
+    "PUSH AF",
+    "OR {d8}",
+    "RST $30",
+    "LD HL, SP{e8}",
+    "LD SP, HL",
+    "LD A, ({a16})",
+    "EI",
+    "",
+    "",
+    "CP {d8}",
+    "RST $38",
+];
+
+// Operands of the CB-prefixed opcodes, by their low three bits
+const CB_REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+
+// Shifts and rotates make up the first quarter of the CB opcodes
+const CB_SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+// One decoded instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub text: String,
+    pub bytes: Vec<u8>,      // The opcode followed by its operands
+    pub target: Option<u16>, // Address operand: a jump or call target, or memory accessed
+}
+
+impl Instruction {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    // The text with the target address replaced by `label`, if there is one
+    pub fn labelled(&self, label: Option<&str>) -> String {
+        match (self.target, label) {
+            (Some(target), Some(label)) => self.text.replace(&format!("${:04X}", target), label),
+            _ => self.text.clone(),
+        }
+    }
+}
+
+// Bytes taken by an instruction starting with `opcode`, operands included
+pub fn length(opcode: u8) -> u16 {
+    let mnemonic = MNEMONICS[opcode as usize];
+    if opcode == 0xCB {
+        2
+    } else if mnemonic.contains("16}") {
+        3
+    } else if mnemonic.contains('{') {
+        2
+    } else {
+        1
+    }
+}
+
+// Decode the instruction at the start of `bytes`, which were read from
+// `addr`. Operands past the end of `bytes` read as 0.
+pub fn disassemble(bytes: &[u8], addr: u16) -> Instruction {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let length = length(opcode) as usize;
+    let bytes = (0..length).map(byte).collect();
+
+    let mnemonic = MNEMONICS[opcode as usize];
+    let d8 = byte(1);
+    let d16 = u16::from_le_bytes([byte(1), byte(2)]);
+    let offset = d8 as i8;
+    let relative = addr.wrapping_add(2).wrapping_add(offset as u16);
+    let target = if mnemonic.contains("{a8}") {
+        Some(0xFF00 | d8 as u16)
+    } else if mnemonic.contains("{a16}") {
+        Some(d16)
+    } else if mnemonic.contains("{r8}") {
+        Some(relative)
+    } else {
+        None
+    };
+
+    let text = match opcode {
+        0xCB => cb_mnemonic(d8),
+        _ if mnemonic.is_empty() => format!("DB ${:02X}", opcode),
+        _ => mnemonic
+            .replace("{d8}", &format!("${:02X}", d8))
+            .replace("{d16}", &format!("${:04X}", d16))
+            .replace("{a8}", &format!("$FF{:02X}", d8))
+            .replace("{a16}", &format!("${:04X}", d16))
+            .replace("{r8}", &format!("${:04X}", relative))
+            .replace("{e8}", &format!("{:+}", offset)),
+    };
+    Instruction {
+        text,
+        bytes,
+        target,
+    }
+}
+
+// Decode the instruction at `addr` as the CPU would see it, without
+// triggering watchpoints
+pub fn disassemble_at(mmu: &MMU, addr: u16) -> Instruction {
+    let bytes: Vec<u8> = (0..3)
+        .map(|i| mmu.peek_byte(addr.wrapping_add(i)))
+        .collect();
+    disassemble(&bytes, addr)
+}
+
+fn cb_mnemonic(opcode: u8) -> String {
+    let register = CB_REGISTERS[(opcode & 0x07) as usize];
+    let bit = (opcode >> 3) & 0x07;
+    match opcode >> 6 {
+        0 => format!("{} {}", CB_SHIFTS[bit as usize], register),
+        1 => format!("BIT {}, {}", bit, register),
+        2 => format!("RES {}, {}", bit, register),
+        _ => format!("SET {}, {}", bit, register),
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+
+// Something the emulated machine ran into that the frontend should hear about
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmuError {
+    // One of the opcodes the SM83 doesn't have. Real hardware locks up and
+    // so does the emulated CPU, until it's reset or PC is moved.
+    IllegalOpcode { pc: u16, opcode: u8 },
+    // A real instruction the CPU doesn't emulate yet. It's skipped.
+    UnimplementedOpcode { pc: u16, opcode: u8 },
+    UnimplementedCbOpcode { pc: u16, opcode: u8 },
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmuError::IllegalOpcode { pc, opcode } => write!(
+                f,
+                "Illegal opcode 0x{:02X} at PC 0x{:04X}, the CPU has locked up",
+                opcode, pc
+            ),
+            EmuError::UnimplementedOpcode { pc, opcode } => write!(
+                f,
+                "Unimplemented opcode 0x{:02X} at PC 0x{:04X}",
+                opcode, pc
+            ),
+            EmuError::UnimplementedCbOpcode { pc, opcode } => write!(
+                f,
+                "Unimplemented CB-prefixed opcode 0x{:02X} at PC 0x{:04X}",
+                opcode, pc
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EmuError {}
+
+// Why a game or boot ROM couldn't be loaded
+#[derive(Debug)]
+pub enum RomLoadError {
+    Read { path: String, error: std::io::Error },
+    TooSmall(usize),    // Not even big enough for a cartridge header
+    BootRomSize(usize), // Boot ROMs are 256 bytes, or 2304 on the CGB
+}
+
+impl fmt::Display for RomLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomLoadError::Read { path, error } => write!(f, "Failed to read {}: {}", path, error),
+            RomLoadError::TooSmall(len) => write!(
+                f,
+                "ROM is only {} bytes, too small to hold a cartridge header",
+                len
+            ),
+            RomLoadError::BootRomSize(len) => {
+                write!(f, "Boot ROM must be 256 or 2304 bytes, got {}", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RomLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomLoadError::Read { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Why a save state couldn't be written or restored
+#[derive(Debug)]
+pub enum SaveError {
+    Io { path: String, error: std::io::Error },
+    NotASaveState,
+    UnsupportedVersion { found: u16, expected: u16 },
+    WrongModel,
+    WrongGame,
+    Corrupt(String), // Truncated or holding values that make no sense
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io { path, error } => write!(f, "{}: {}", path, error),
+            SaveError::NotASaveState => write!(f, "Not a rustboy save state"),
+            SaveError::UnsupportedVersion { found, expected } => write!(
+                f,
+                "Save state version {} is not supported (expected {})",
+                found, expected
+            ),
+            SaveError::WrongModel => write!(f, "Save state was made on a different model"),
+            SaveError::WrongGame => write!(f, "Save state was made with a different game"),
+            SaveError::Corrupt(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// The state readers report problems with the data as strings
+impl From<String> for SaveError {
+    fn from(reason: String) -> Self {
+        SaveError::Corrupt(reason)
+    }
+}
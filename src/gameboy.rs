@@ -0,0 +1,300 @@
+use crate::audio::{Audio, CYCLES_PER_FRAME};
+use crate::cartridge::Header;
+use crate::cpu::CPU;
+use crate::debug::Stop;
+use crate::error::{EmuError, RomLoadError, SaveError};
+use crate::joypad::Buttons;
+use crate::mmu::MMU;
+use crate::model::Model;
+use crate::png;
+use crate::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::savestate::{StateReader, StateWriter, MAGIC, VERSION};
+use crate::serial::LinkDevice;
+use crate::symbols::Symbols;
+
+// How to set up a new machine
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub model: Option<Model>, // Picked from the cartridge header when not given
+    pub boot_rom: Option<Vec<u8>>, // Run before the cartridge; skipped when not given
+}
+
+// The whole emulated machine. Everything that makes up the state of a running
+// game lives here, so it can be saved and restored as a unit.
+pub struct GameBoy {
+    pub cpu: CPU,
+    pub mmu: MMU,
+    pub audio: Audio,
+    pub ppu: Ppu, // Only holds the framebuffer, so it isn't part of save states
+    pub model: Model,
+    pub header: Header,
+    pub symbols: Symbols,        // Labels for debugging, not part of save states
+    pub cycles: u64,             // Clock cycles run since power on, not part of save states
+    pub continue_on_error: bool, // Log errors and run on instead of stopping; not part of save states
+}
+
+// Errors a run carried on past. The first is logged straight away and the
+// rest only counted, so a game stuck on an unimplemented opcode doesn't
+// flood the log.
+#[derive(Default)]
+pub(crate) struct SkippedErrors {
+    count: u32,
+}
+
+impl SkippedErrors {
+    pub(crate) fn skip(&mut self, error: EmuError) {
+        if self.count == 0 {
+            log::error!(target: "cpu", "{}", error);
+        }
+        self.count += 1;
+    }
+
+    pub(crate) fn finish(self) {
+        if self.count > 1 {
+            log::error!(target: "cpu", "...and {} more errors", self.count - 1);
+        }
+    }
+}
+
+impl GameBoy {
+    // Fails if the ROM is too small to be a game or the boot ROM in `config`
+    // isn't a valid image
+    pub fn new(rom: &[u8], config: Config) -> Result<Self, RomLoadError> {
+        let header = Header::parse(rom);
+        let model = config.model.unwrap_or_else(|| Model::from_header(&header));
+
+        let mut mmu = MMU::new(model);
+        mmu.load_rom(rom)?;
+        if let Some(boot_rom) = config.boot_rom {
+            mmu.load_boot_rom(boot_rom)?;
+        }
+
+        let mut gameboy = Self {
+            cpu: CPU::new(model),
+            mmu,
+            audio: Audio::new(model),
+            ppu: Ppu::new(),
+            model,
+            header,
+            symbols: Symbols::default(),
+            cycles: 0,
+            continue_on_error: false,
+        };
+
+        // Without a boot ROM, start from the state it would have left behind
+        if !gameboy.mmu.boot_rom_mapped() {
+            gameboy.skip_boot_rom();
+        }
+        Ok(gameboy)
+    }
+
+    // Start at the cartridge entry point with the state the boot ROM would have left
+    fn skip_boot_rom(&mut self) {
+        self.cpu.skip_boot_rom(&self.header);
+        self.mmu.apply_post_boot_state();
+    }
+
+    // Execute a single instruction, with the rest of the hardware kept in
+    // step, and return the clock cycles it took. An instruction that fails
+    // still takes its time. The trace history is dumped for an error that
+    // stops the machine, but not for each one it carries on past.
+    pub fn step(&mut self) -> Result<u32, EmuError> {
+        let result = self.cpu.step(&mut self.mmu);
+        let cycles = match &result {
+            Ok(cycles) => *cycles,
+            Err(error) => {
+                if !self.continue_on_error {
+                    self.cpu.trace.dump(&error.to_string(), &self.symbols);
+                }
+                CPU::error_cycles(error)
+            }
+        };
+        self.mmu.tick(cycles);
+        self.cycles += cycles as u64;
+        result
+    }
+
+    // Run instructions until at least `cycles` clock cycles have passed,
+    // stopping early when a breakpoint or watchpoint is hit, or on an error
+    // unless `continue_on_error` is set
+    pub fn run_cycles(&mut self, cycles: u32) -> Result<(), EmuError> {
+        self.run_cycles_until(cycles, |_| false).map(|_| ())
+    }
+
+    // Like `run_cycles`, but also stop before any instruction `done` is true
+    // for. Returns whether it was `done` that stopped the run.
+    pub fn run_cycles_until(
+        &mut self,
+        cycles: u32,
+        mut done: impl FnMut(&GameBoy) -> bool,
+    ) -> Result<bool, EmuError> {
+        let end = self.cycles + cycles as u64;
+        let mut skipped = SkippedErrors::default();
+        let mut result = Ok(false);
+        while self.cycles < end && !self.debug_stop_pending() {
+            if done(self) {
+                result = Ok(true);
+                break;
+            }
+            match self.step() {
+                Err(error) if self.continue_on_error => skipped.skip(error),
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+                Ok(_) => {}
+            }
+        }
+        skipped.finish();
+        result
+    }
+
+    // Emulate one frame, then draw the screen and generate the frame's audio.
+    // The frame is finished even if it was cut short.
+    pub fn run_frame(&mut self) -> Result<(), EmuError> {
+        let result = self.run_cycles(CYCLES_PER_FRAME);
+        self.finish_frame();
+        result
+    }
+
+    // Draw the screen and generate the audio for the frame just emulated
+    pub fn finish_frame(&mut self) {
+        self.ppu.render_tile_map(&self.mmu);
+        self.audio.update(CYCLES_PER_FRAME);
+    }
+
+    // The screen as of the last frame: 160x144 pixels, 3 bytes (RGB) each
+    pub fn framebuffer(&self) -> &[u8] {
+        self.ppu.framebuffer()
+    }
+
+    // Interleaved stereo samples at `audio::SAMPLE_RATE` generated by the last frame
+    pub fn audio_samples(&self) -> &[i16] {
+        self.audio.samples()
+    }
+
+    // Write the screen to a PNG file, `scale` times its native size
+    pub fn save_screenshot(&self, path: &str, scale: u32) -> std::io::Result<()> {
+        let scale = scale.max(1);
+        png::write_rgb(
+            path,
+            SCREEN_WIDTH * scale,
+            SCREEN_HEIGHT * scale,
+            &self.ppu.scaled_framebuffer(scale),
+        )
+    }
+
+    // Whether the last step hit a breakpoint or watchpoint, clearing it
+    pub fn take_debug_stop(&mut self) -> Option<Stop> {
+        self.cpu
+            .breakpoints
+            .take_hit()
+            .map(Stop::Breakpoint)
+            .or_else(|| self.mmu.watchpoints.take_hit())
+    }
+
+    // Whether a breakpoint or watchpoint was hit and is still to be taken
+    pub fn debug_stop_pending(&self) -> bool {
+        self.cpu.breakpoints.is_hit() || self.mmu.watchpoints.is_hit()
+    }
+
+    // Plug something into the link port, replacing whatever was there
+    pub fn set_link_device(&mut self, device: Box<dyn LinkDevice>) {
+        self.mmu.serial.device = device;
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.mmu.buttons = buttons;
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.write_bytes(MAGIC);
+        w.write_u16(VERSION);
+
+        // Identify the game and hardware so a state can't be loaded into the wrong one
+        w.write_u8(self.model.id());
+        w.write_u8(self.header.header_checksum);
+        w.write_u8(self.header.title_checksum());
+
+        self.cpu.save_state(&mut w);
+        self.mmu.save_state(&mut w);
+        self.audio.save_state(&mut w);
+        w.into_bytes()
+    }
+
+    // Restore a state created by `save_state`. If the state turns out to be
+    // invalid partway through, the machine is put back the way it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        let backup = self.save_state();
+        self.read_state(data).inspect_err(|_| {
+            self.read_state(&backup)
+                .expect("Failed to restore state after a bad load");
+        })?;
+
+        // Show the restored state straight away instead of the last frame
+        self.ppu.render_tile_map(&self.mmu);
+        Ok(())
+    }
+
+    fn read_state(&mut self, data: &[u8]) -> Result<(), SaveError> {
+        let mut r = StateReader::new(data);
+        if r.read_bytes()? != MAGIC {
+            return Err(SaveError::NotASaveState);
+        }
+
+        // Older versions would be migrated here as the format evolves
+        let version = r.read_u16()?;
+        if version != VERSION {
+            return Err(SaveError::UnsupportedVersion {
+                found: version,
+                expected: VERSION,
+            });
+        }
+
+        if Model::from_id(r.read_u8()?) != Some(self.model) {
+            return Err(SaveError::WrongModel);
+        }
+        let header_checksum = r.read_u8()?;
+        let title_checksum = r.read_u8()?;
+        if header_checksum != self.header.header_checksum
+            || title_checksum != self.header.title_checksum()
+        {
+            return Err(SaveError::WrongGame);
+        }
+
+        self.cpu.load_state(&mut r)?;
+        self.mmu.load_state(&mut r)?;
+        self.audio.load_state(&mut r)?;
+        if !r.is_at_end() {
+            return Err(SaveError::Corrupt(
+                "Save state has unexpected trailing data".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn save_state_to_file(&self, path: &str) -> Result<(), SaveError> {
+        std::fs::write(path, self.save_state()).map_err(|error| SaveError::Io {
+            path: path.to_string(),
+            error,
+        })
+    }
+
+    pub fn load_state_from_file(&mut self, path: &str) -> Result<(), SaveError> {
+        let data = std::fs::read(path).map_err(|error| SaveError::Io {
+            path: path.to_string(),
+            error,
+        })?;
+        self.load_state(&data)
+    }
+}
+
+// Save state slots live next to the ROM: game.gb -> game.ss1 ... game.ss10
+pub fn state_slot_path(rom_path: &str, slot: u8) -> String {
+    let stem = match rom_path.rfind('.') {
+        Some(dot) if !rom_path[dot..].contains(['/', '\\']) => &rom_path[..dot],
+        _ => rom_path,
+    };
+    format!("{}.ss{}", stem, slot)
+}
@@ -0,0 +1,225 @@
+use crate::cpu::CPU;
+use crate::mmu::MMU;
+
+const HEADER_SIZE: usize = 0x70;
+
+// Address the init and play routines return to. Nothing is mapped here in a
+// GBS rip, so reaching it means the routine has finished.
+const RETURN_ADDRESS: u16 = 0x00F0;
+
+// Upper bound on instructions per routine call so a broken rip can't hang the player
+const MAX_INSTRUCTIONS_PER_CALL: u32 = 1_000_000;
+
+// Rate of the VBlank interrupt, used when the rip doesn't request the timer.
+// This is also the frame rate `run_frame` is called at.
+const VBLANK_RATE: f64 = 4194304.0 / 70224.0;
+
+// A Game Boy Sound System rip: the sound driver and music data from a game
+// plus the addresses needed to drive it
+pub struct GbsFile {
+    pub version: u8,
+    pub song_count: u8,
+    pub first_song: u8, // 1-based
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    pub data: Vec<u8>,
+}
+
+impl GbsFile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        Self::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE {
+            return Err("File is too small to contain a GBS header".to_string());
+        }
+        if &bytes[0..3] != b"GBS" {
+            return Err("Missing GBS signature".to_string());
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let text = |offset: usize| {
+            let field = &bytes[offset..offset + 32];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).trim().to_string()
+        };
+
+        let gbs = Self {
+            version: bytes[0x03],
+            song_count: bytes[0x04],
+            first_song: bytes[0x05],
+            load_address: word(0x06),
+            init_address: word(0x08),
+            play_address: word(0x0A),
+            stack_pointer: word(0x0C),
+            timer_modulo: bytes[0x0E],
+            timer_control: bytes[0x0F],
+            title: text(0x10),
+            author: text(0x30),
+            copyright: text(0x50),
+            data: bytes[HEADER_SIZE..].to_vec(),
+        };
+
+        if gbs.version != 1 {
+            return Err(format!("Unsupported GBS version {}", gbs.version));
+        }
+        if gbs.song_count == 0 {
+            return Err("GBS file contains no songs".to_string());
+        }
+        if gbs.load_address < 0x0400 || gbs.load_address >= 0x8000 {
+            return Err(format!("Invalid load address 0x{:04X}", gbs.load_address));
+        }
+
+        Ok(gbs)
+    }
+
+    pub fn uses_timer(&self) -> bool {
+        self.timer_control & 0x04 != 0
+    }
+
+    // How many times per second the play routine should be called
+    pub fn play_rate(&self) -> f64 {
+        if !self.uses_timer() {
+            return VBLANK_RATE;
+        }
+
+        let input_clock = match self.timer_control & 0x03 {
+            0 => 4096.0,
+            1 => 262144.0,
+            2 => 65536.0,
+            _ => 16384.0,
+        };
+        // Bit 7 selects CGB double speed, which also doubles the timer
+        let speed = if self.timer_control & 0x80 != 0 {
+            2.0
+        } else {
+            1.0
+        };
+        input_clock * speed / (256.0 - self.timer_modulo as f64)
+    }
+}
+
+// Drives the CPU through a GBS file's init and play routines, standing in for
+// the VBlank or timer interrupt that would normally call the player
+pub struct GbsPlayer {
+    pub gbs: GbsFile,
+    pub track: u8, // 0-based
+    pending_calls: f64,
+    failed: bool, // A routine went wrong, so the track is stopped until another starts
+}
+
+impl GbsPlayer {
+    pub fn new(gbs: GbsFile) -> Self {
+        let track = gbs.first_song.saturating_sub(1).min(gbs.song_count - 1);
+        Self {
+            gbs,
+            track,
+            pending_calls: 0.0,
+            failed: false,
+        }
+    }
+
+    // Reset the machine, load the rip and run the init routine for `track`
+    pub fn start_track(&mut self, cpu: &mut CPU, mmu: &mut MMU, track: u8) {
+        self.track = track.min(self.gbs.song_count - 1);
+        self.pending_calls = 0.0;
+        self.failed = false;
+
+        *cpu = CPU::new(cpu.model);
+        *mmu = MMU::new(mmu.model);
+        cpu.interrupts_enabled = false;
+
+        // The rip is a ROM image from the load address on, banked past 0x4000
+        // so drivers can switch in their music data
+        let load_address = self.gbs.load_address as usize;
+        let mut rom = vec![0; load_address];
+        rom.extend_from_slice(&self.gbs.data);
+
+        // RST vectors are relocated to the load address
+        for vector in (0x00..0x40).step_by(8) {
+            let target = self.gbs.load_address + vector as u16;
+            rom[vector] = 0xC3; // JP nn
+            rom[vector + 1] = target as u8;
+            rom[vector + 2] = (target >> 8) as u8;
+        }
+        mmu.map_banked_rom(rom);
+
+        mmu.write_byte(0xFF06, self.gbs.timer_modulo); // TMA
+        mmu.write_byte(0xFF07, self.gbs.timer_control); // TAC
+
+        cpu.registers.sp = self.gbs.stack_pointer;
+        cpu.registers.a = self.track;
+        self.call_or_stop(cpu, mmu, self.gbs.init_address);
+
+        log::info!(
+            "Playing track {}/{}: {} - {}",
+            self.track + 1,
+            self.gbs.song_count,
+            self.gbs.title,
+            self.gbs.author
+        );
+    }
+
+    pub fn next_track(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let track = (self.track + 1) % self.gbs.song_count;
+        self.start_track(cpu, mmu, track);
+    }
+
+    pub fn previous_track(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        let track = self.track.checked_sub(1).unwrap_or(self.gbs.song_count - 1);
+        self.start_track(cpu, mmu, track);
+    }
+
+    // Call the play routine as many times as its rate demands for one frame
+    pub fn run_frame(&mut self, cpu: &mut CPU, mmu: &mut MMU) {
+        self.pending_calls += self.gbs.play_rate() / VBLANK_RATE;
+        while self.pending_calls >= 1.0 && !self.failed {
+            self.pending_calls -= 1.0;
+            self.call_or_stop(cpu, mmu, self.gbs.play_address);
+        }
+    }
+
+    // Call the routine at `addr`, stopping the track with a single log
+    // message if it fails
+    fn call_or_stop(&mut self, cpu: &mut CPU, mmu: &mut MMU, addr: u16) {
+        if let Err(e) = Self::call(cpu, mmu, addr) {
+            log::error!(target: "cpu", "{}, stopping track {}", e, self.track + 1);
+            self.failed = true;
+        }
+    }
+
+    // Push the return address and run the routine at `addr` until it
+    // returns. Gives up on the first error, including the illegal opcode that
+    // locks the CPU up, or if the routine never returns.
+    fn call(cpu: &mut CPU, mmu: &mut MMU, addr: u16) -> Result<(), String> {
+        cpu.registers.sp = cpu.registers.sp.wrapping_sub(2);
+        mmu.write_byte(cpu.registers.sp, RETURN_ADDRESS as u8);
+        mmu.write_byte(
+            cpu.registers.sp.wrapping_add(1),
+            (RETURN_ADDRESS >> 8) as u8,
+        );
+        cpu.registers.pc = addr;
+
+        for _ in 0..MAX_INSTRUCTIONS_PER_CALL {
+            if cpu.registers.pc == RETURN_ADDRESS {
+                return Ok(());
+            }
+            cpu.step(mmu)
+                .map_err(|e| format!("GBS routine at 0x{:04X} failed: {}", addr, e))?;
+        }
+
+        Err(format!(
+            "GBS routine at 0x{:04X} did not return, PC: 0x{:04X}",
+            addr, cpu.registers.pc
+        ))
+    }
+}
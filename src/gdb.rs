@@ -0,0 +1,358 @@
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::debug::{Breakpoint, Stop, Watchpoint};
+use crate::gameboy::GameBoy;
+
+// GDB has no SM83 target, so registers are sent in this order, each 16 bits
+// and little-endian like everything else in the protocol
+const REGISTERS: [&str; 6] = ["AF", "BC", "DE", "HL", "SP", "PC"];
+
+// Stop signals
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+// What to do once GDB lets the game go
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GdbResume {
+    Continue, // Run until the next breakpoint, watchpoint or interrupt
+    Detach,   // GDB has gone; run on without it
+    Kill,     // Quit the emulator
+}
+
+// A GDB remote serial protocol server. While the game is stopped `stop`
+// serves GDB's requests; it returns when GDB continues, detaches or kills
+// the game. Breakpoints and watchpoints set through GDB are the same ones
+// the built-in debugger uses.
+pub struct GdbStub {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    running: bool, // GDB is waiting for a stop reply
+}
+
+impl GdbStub {
+    // Wait for GDB to connect on `port`
+    pub fn listen(port: u16) -> std::io::Result<Self> {
+        // Whoever connects can read and write memory, so only this machine may
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        log::info!("Waiting for GDB to connect on port {}", port);
+        let (stream, peer) = listener.accept()?;
+        log::info!("GDB connected from {}", peer);
+        Self::new(stream)
+    }
+
+    pub fn new(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            running: false,
+        })
+    }
+
+    // Whether GDB has asked to interrupt the running game, without waiting
+    pub fn interrupted(&mut self) -> bool {
+        if self.reader.get_ref().set_nonblocking(true).is_err() {
+            return false;
+        }
+        let interrupted = match self.reader.fill_buf() {
+            Ok(buffer) => {
+                let interrupted = buffer.contains(&0x03);
+                let len = buffer.len();
+                self.reader.consume(len);
+                interrupted
+            }
+            Err(_) => false,
+        };
+        let _ = self.reader.get_ref().set_nonblocking(false);
+        interrupted
+    }
+
+    // The game has stopped, on a breakpoint or watchpoint if `stop` says so
+    // and otherwise because GDB asked. Serve GDB until it lets the game go.
+    pub fn stop(&mut self, gameboy: &mut GameBoy, stop: Option<Stop>) -> GdbResume {
+        match self.serve(gameboy, stop) {
+            Ok(resume) => resume,
+            Err(e) => {
+                log::warn!("Lost the GDB connection: {}", e);
+                GdbResume::Detach
+            }
+        }
+    }
+
+    fn serve(&mut self, gameboy: &mut GameBoy, stop: Option<Stop>) -> std::io::Result<GdbResume> {
+        if std::mem::take(&mut self.running) {
+            // Without a breakpoint or watchpoint, GDB must have interrupted
+            self.send(&stop_reply(gameboy, stop, SIGINT))?;
+        }
+
+        loop {
+            let Some(packet) = self.receive()? else {
+                return Ok(GdbResume::Detach);
+            };
+            let reply = match packet.as_bytes().first() {
+                Some(b'c') => {
+                    gameboy.cpu.breakpoints.resume();
+                    self.running = true;
+                    return Ok(GdbResume::Continue);
+                }
+                Some(b's') => {
+                    gameboy.cpu.breakpoints.resume();
+                    let stop = match gameboy.step() {
+                        Ok(_) => gameboy.take_debug_stop(),
+                        Err(e) => Some(Stop::Error(e)),
+                    };
+                    stop_reply(gameboy, stop, SIGTRAP)
+                }
+                Some(b'D') => {
+                    self.send("OK")?;
+                    return Ok(GdbResume::Detach);
+                }
+                Some(b'k') => return Ok(GdbResume::Kill),
+                _ => handle(gameboy, &packet).unwrap_or_else(|| "E01".to_string()),
+            };
+            self.send(&reply)?;
+        }
+    }
+
+    // Read the next packet, acknowledging it. None once GDB disconnects.
+    fn receive(&mut self) -> std::io::Result<Option<String>> {
+        // Skip acknowledgements and interrupts until a packet starts
+        loop {
+            let mut byte = [0u8];
+            match std::io::Read::read(&mut self.reader, &mut byte)? {
+                0 => return Ok(None),
+                _ if byte[0] == b'$' => break,
+                _ => {}
+            }
+        }
+
+        let mut packet = Vec::new();
+        if self.reader.read_until(b'#', &mut packet)? == 0 {
+            return Ok(None);
+        }
+        packet.pop();
+        let mut checksum = [0u8; 2];
+        std::io::Read::read_exact(&mut self.reader, &mut checksum)?;
+
+        let expected = std::str::from_utf8(&checksum)
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if expected != Some(checksum_of(&packet)) {
+            self.writer.write_all(b"-")?;
+            return self.receive();
+        }
+        self.writer.write_all(b"+")?;
+        Ok(Some(String::from_utf8_lossy(&packet).into_owned()))
+    }
+
+    fn send(&mut self, data: &str) -> std::io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.writer.write_all(packet.as_bytes())?;
+
+        // Wait for the acknowledgement, resending if GDB asks
+        loop {
+            let mut byte = [0u8];
+            match std::io::Read::read(&mut self.reader, &mut byte) {
+                Ok(0) => return Ok(()),
+                Ok(_) if byte[0] == b'+' => return Ok(()),
+                Ok(_) if byte[0] == b'-' => self.writer.write_all(packet.as_bytes())?,
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// Answer a packet that doesn't resume the game. None for a malformed
+// request; an empty reply tells GDB the request isn't supported.
+fn handle(gameboy: &mut GameBoy, packet: &str) -> Option<String> {
+    let Some(command) = packet.get(..1) else {
+        return Some(String::new());
+    };
+    let args = &packet[1..];
+    let reply = match command {
+        "?" => stop_reply(gameboy, None, SIGTRAP),
+        "g" => {
+            let values = register_values(gameboy);
+            values.iter().map(|&value| hex_u16(value)).collect()
+        }
+        "G" => {
+            for (i, chunk) in args.as_bytes().chunks(4).take(REGISTERS.len()).enumerate() {
+                let value = parse_u16_le(std::str::from_utf8(chunk).ok()?)?;
+                set_register(gameboy, i, value);
+            }
+            "OK".to_string()
+        }
+        "p" => {
+            let register = usize::from_str_radix(args, 16).ok()?;
+            hex_u16(*register_values(gameboy).get(register)?)
+        }
+        "P" => {
+            let (register, value) = args.split_once('=')?;
+            let register = usize::from_str_radix(register, 16).ok()?;
+            if register >= REGISTERS.len() {
+                return None;
+            }
+            set_register(gameboy, register, parse_u16_le(value)?);
+            "OK".to_string()
+        }
+        "m" => {
+            let (addr, len) = parse_range(args)?;
+            (0..len)
+                .map(|i| format!("{:02x}", gameboy.mmu.peek_byte(addr.wrapping_add(i))))
+                .collect()
+        }
+        "M" => {
+            let (range, data) = args.split_once(':')?;
+            let (addr, len) = parse_range(range)?;
+            let bytes = parse_hex_bytes(data)?;
+            if bytes.len() != len as usize {
+                return None;
+            }
+            for (i, byte) in bytes.into_iter().enumerate() {
+                gameboy.mmu.write_byte(addr.wrapping_add(i as u16), byte);
+            }
+            // GDB's own writes shouldn't trip a watchpoint
+            gameboy.take_debug_stop();
+            "OK".to_string()
+        }
+        "Z" | "z" => {
+            let mut fields = args.split(',');
+            let kind = fields.next()?;
+            let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+            let len = u16::from_str_radix(fields.next()?, 16).ok()?;
+            set_breakpoint(gameboy, command == "Z", kind, addr, len)?;
+            "OK".to_string()
+        }
+        "q" if args == "Attached" => "1".to_string(),
+        "q" if args.starts_with("Supported") => "PacketSize=1000".to_string(),
+        "H" => "OK".to_string(),
+        _ => String::new(),
+    };
+    Some(reply)
+}
+
+fn set_breakpoint(
+    gameboy: &mut GameBoy,
+    insert: bool,
+    kind: &str,
+    addr: u16,
+    len: u16,
+) -> Option<()> {
+    match kind {
+        // Software and hardware breakpoints are the same thing here
+        "0" | "1" => {
+            let breakpoints = &mut gameboy.cpu.breakpoints.list;
+            let breakpoint = Breakpoint::Pc(addr);
+            breakpoints.retain(|&b| b != breakpoint);
+            if insert {
+                breakpoints.push(breakpoint);
+            }
+        }
+        "2" | "3" | "4" => {
+            let watchpoint = Watchpoint {
+                start: addr,
+                end: addr.saturating_add(len.max(1) - 1),
+                on_read: kind != "2",
+                on_write: kind != "3",
+            };
+            let watchpoints = &mut gameboy.mmu.watchpoints.list;
+            watchpoints.retain(|&w| w != watchpoint);
+            if insert {
+                watchpoints.push(watchpoint);
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+// Tell GDB why the game stopped: `stop`, or else `signal`. A watchpoint is
+// reported as the kind GDB set, which for an access watchpoint means one
+// that watches both reads and writes.
+fn stop_reply(gameboy: &GameBoy, stop: Option<Stop>, signal: u8) -> String {
+    match stop {
+        Some(Stop::Watchpoint { addr, write, .. }) => {
+            let one_way = gameboy.mmu.watchpoints.list.iter().any(|w| {
+                (w.start..=w.end).contains(&addr)
+                    && if write {
+                        w.on_write && !w.on_read
+                    } else {
+                        w.on_read && !w.on_write
+                    }
+            });
+            let kind = match (one_way, write) {
+                (false, _) => "awatch",
+                (true, true) => "watch",
+                (true, false) => "rwatch",
+            };
+            format!("T{:02x}{}:{:04x};", SIGTRAP, kind, addr)
+        }
+        Some(Stop::Breakpoint(_)) => format!("S{:02x}", SIGTRAP),
+        Some(Stop::Error(_)) => format!("S{:02x}", SIGILL),
+        None => format!("S{:02x}", signal),
+    }
+}
+
+fn register_values(gameboy: &GameBoy) -> [u16; 6] {
+    let r = &gameboy.cpu.registers;
+    [r.af(), r.bc(), r.de(), r.hl(), r.sp, r.pc]
+}
+
+fn set_register(gameboy: &mut GameBoy, register: usize, value: u16) {
+    let r = &mut gameboy.cpu.registers;
+    match register {
+        0 => r.set_af(value),
+        1 => r.set_bc(value),
+        2 => r.set_de(value),
+        3 => r.set_hl(value),
+        4 => r.sp = value,
+        // Moving PC frees a CPU locked up by an illegal opcode
+        _ if value != r.pc => {
+            r.pc = value;
+            gameboy.cpu.locked = false;
+        }
+        _ => {}
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn hex_u16(value: u16) -> String {
+    value
+        .to_le_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn parse_u16_le(hex: &str) -> Option<u16> {
+    match parse_hex_bytes(hex)?.as_slice() {
+        &[low, high] => Some(u16::from_le_bytes([low, high])),
+        _ => None,
+    }
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// "addr,len"
+fn parse_range(args: &str) -> Option<(u16, u16)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        u16::from_str_radix(addr, 16).ok()?,
+        u16::from_str_radix(len, 16).ok()?,
+    ))
+}
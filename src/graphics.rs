@@ -1,147 +1,335 @@
 extern crate sdl2;
 
+use crate::input::Hotkey;
+use crate::viewer::{Viewer, ViewerKind};
+use rustboy::joypad::Button;
+use rustboy::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
-use sdl2::render::TextureCreator;
-use sdl2::video::WindowContext;
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::video::{Window, WindowContext};
 use sdl2::Sdl;
 
-const SCREEN_WIDTH: u32 = 160;
-const SCREEN_HEIGHT: u32 = 144;
+// Scale from Game Boy pixels to window pixels
+const SCALE: u32 = 4;
 
 pub struct Graphics {
     sdl_context: Sdl,
-    canvas: sdl2::render::Canvas<sdl2::video::Window>,
-    texture_creator: TextureCreator<WindowContext>,
-    framebuffer: [u8; (SCREEN_WIDTH * SCREEN_HEIGHT * 3) as usize], // 3 bytes per pixel (RGB)
-    event_pump: sdl2::EventPump,                                    // Add event handling
+    // One per window. With several screens they are either side by side in
+    // a single window or each in a window of its own.
+    canvases: Vec<(Canvas<Window>, TextureCreator<WindowContext>)>,
+    screens_per_window: usize,
+    viewers: Vec<Viewer>,        // Debug windows, each closed on its own
+    event_pump: sdl2::EventPump, // Add event handling
 }
 
 impl Graphics {
     pub fn new() -> Self {
+        Self::with_screens(1, false)
+    }
+
+    // A window showing `screens` Game Boy screens side by side, or with
+    // `separate_windows` a window for each
+    pub fn with_screens(screens: usize, separate_windows: bool) -> Self {
         // Initialize SDL2
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
-        // Create the window
-        let window = video_subsystem
-            .window("GBC Emulator", SCREEN_WIDTH * 4, SCREEN_HEIGHT * 4)
-            .position_centered()
-            .build()
-            .unwrap();
+        let (windows, screens_per_window) = if separate_windows {
+            (screens, 1)
+        } else {
+            (1, screens)
+        };
 
-        // Create the canvas and texture creator
-        let mut canvas = window.into_canvas().build().unwrap();
-        let texture_creator = canvas.texture_creator();
+        let canvases = (0..windows)
+            .map(|_| {
+                // Create the window
+                let window = video_subsystem
+                    .window(
+                        "GBC Emulator",
+                        SCREEN_WIDTH * SCALE * screens_per_window as u32,
+                        SCREEN_HEIGHT * SCALE,
+                    )
+                    .position_centered()
+                    .build()
+                    .unwrap();
 
-        // Set the canvas color (black)
-        canvas.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
-        canvas.clear();
-        canvas.present();
+                // Create the canvas and texture creator
+                let mut canvas = window.into_canvas().build().unwrap();
+                let texture_creator = canvas.texture_creator();
+
+                // Set the canvas color (black)
+                canvas.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
+                canvas.clear();
+                canvas.present();
+                (canvas, texture_creator)
+            })
+            .collect();
 
         // Event pump for handling SDL events
         let event_pump = sdl_context.event_pump().unwrap();
 
         Self {
             sdl_context,
-            canvas,
-            texture_creator,
-            framebuffer: [0; (SCREEN_WIDTH * SCREEN_HEIGHT * 3) as usize], // Initialize framebuffer to black
-            event_pump,                                                    // Initialize event pump
+            canvases,
+            screens_per_window,
+            viewers: Vec::new(),
+            event_pump, // Initialize event pump
         }
     }
 
-    // Render the current frame from the framebuffer
-    pub fn render(&mut self) {
-        let mut texture = self
-            .texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH, SCREEN_HEIGHT)
-            .unwrap();
+    // Show a frame from the emulator's framebuffer
+    pub fn render(&mut self, framebuffer: &[u8]) {
+        self.render_screens(&[framebuffer]);
+    }
 
-        texture
-            .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
-                buffer.copy_from_slice(&self.framebuffer);
-            })
-            .unwrap();
-
-        // Clear the canvas and copy the texture to it
-        self.canvas.clear();
-        self.canvas
-            .copy(
-                &texture,
-                None,
-                Some(Rect::new(0, 0, SCREEN_WIDTH * 4, SCREEN_HEIGHT * 4)),
-            )
-            .unwrap();
-        self.canvas.present();
+    // Show a frame on each screen, in order
+    pub fn render_screens(&mut self, framebuffers: &[&[u8]]) {
+        for (window, (canvas, texture_creator)) in self.canvases.iter_mut().enumerate() {
+            // Clear the canvas and copy each screen's texture to it
+            canvas.clear();
+            let first = window * self.screens_per_window;
+            let screens = framebuffers
+                .iter()
+                .skip(first)
+                .take(self.screens_per_window);
+            for (slot, framebuffer) in screens.enumerate() {
+                let mut texture = texture_creator
+                    .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH, SCREEN_HEIGHT)
+                    .unwrap();
+
+                texture
+                    .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
+                        buffer.copy_from_slice(framebuffer);
+                    })
+                    .unwrap();
+
+                let x = (slot as u32 * SCREEN_WIDTH * SCALE) as i32;
+                canvas
+                    .copy(
+                        &texture,
+                        None,
+                        Some(Rect::new(x, 0, SCREEN_WIDTH * SCALE, SCREEN_HEIGHT * SCALE)),
+                    )
+                    .unwrap();
+            }
+            canvas.present();
+        }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        // Titles come from file metadata, so ignore ones SDL rejects
+        for (canvas, _) in &mut self.canvases {
+            let _ = canvas.window_mut().set_title(title);
+        }
+    }
+
+    // Open a debug window showing an image `width` by `height`, or close it
+    // if it's open
+    pub fn toggle_viewer(&mut self, kind: ViewerKind, title: &str, width: u32, height: u32) {
+        if let Some(i) = self.viewers.iter().position(|viewer| viewer.kind == kind) {
+            self.viewers.remove(i);
+            return;
+        }
+        let viewer = self
+            .sdl_context
+            .video()
+            .and_then(|video| Viewer::new(&video, kind, title, width, height));
+        match viewer {
+            Ok(viewer) => self.viewers.push(viewer),
+            Err(e) => println!("Failed to open the {} window: {}", title, e),
+        }
     }
 
-    // Example: Set a pixel in the framebuffer (used for testing)
-    pub fn set_pixel(&mut self, x: u32, y: u32, r: u8, g: u8, b: u8) {
-        let idx = ((y * SCREEN_WIDTH + x) * 3) as usize;
-        self.framebuffer[idx] = r;
-        self.framebuffer[idx + 1] = g;
-        self.framebuffer[idx + 2] = b;
+    pub fn viewer_mut(&mut self, kind: ViewerKind) -> Option<&mut Viewer> {
+        self.viewers.iter_mut().find(|viewer| viewer.kind == kind)
     }
 
-    // Poll for SDL2 events and return whether to quit
-    pub fn handle_events(&mut self) -> bool {
+    // Poll for SDL2 events, forwarding hotkeys to the input handler, and return whether to quit
+    pub fn handle_events(&mut self, input: &mut crate::input::Input) -> bool {
         for event in self.event_pump.poll_iter() {
+            // Debug windows follow the mouse, and closing one only closes it.
+            // Keys pressed in them still go to the game.
+            let viewer = event
+                .get_window_id()
+                .and_then(|id| self.viewers.iter().position(|v| v.window_id() == id));
+            if let Some(i) = viewer {
+                if let Event::Window {
+                    win_event: WindowEvent::Close,
+                    ..
+                } = event
+                {
+                    self.viewers.remove(i);
+                    continue;
+                }
+                self.viewers[i].handle_event(&event);
+            }
+
+            // Game Boy buttons, which may share keys with hotkeys
+            match event {
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    Self::button(keycode).inspect(|&(player, b)| input.set_button(player, b, true))
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    Self::button(keycode).inspect(|&(player, b)| input.set_button(player, b, false))
+                }
+                _ => None,
+            };
+
             match event {
-                sdl2::event::Event::Quit { .. } => {
+                Event::Quit { .. }
+                | Event::Window {
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
                     return true; // Signal to quit the emulator
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::ToggleAudioRecording),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } if Self::state_slot(keycode).is_some() => {
+                    let slot = Self::state_slot(keycode).unwrap();
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        input.press_hotkey(Hotkey::LoadState(slot));
+                    } else {
+                        input.press_hotkey(Hotkey::SaveState(slot));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => input.set_hotkey_held(Hotkey::Rewind, true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => input.set_hotkey_held(Hotkey::Rewind, false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => input.set_hotkey_held(Hotkey::Turbo, true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => input.set_hotkey_held(Hotkey::Turbo, false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::TogglePause),
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => input.press_hotkey(Hotkey::FrameAdvance),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Equals),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::SpeedUp),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::SlowDown),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::Screenshot),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::Debug),
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::ToggleTileViewer),
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::ToggleMapViewer),
+                Event::KeyDown {
+                    keycode: Some(Keycode::O),
+                    repeat: false,
+                    ..
+                } => input.press_hotkey(Hotkey::ToggleSpriteViewer),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Right),
+                    ..
+                } => input.press_hotkey(Hotkey::NextTrack),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Left),
+                    ..
+                } => input.press_hotkey(Hotkey::PreviousTrack),
                 _ => {}
             }
         }
         false
     }
 
-    // Convert color ID to RGB (using placeholder palette)
-    fn get_color_from_palette(&self, color_id: u8) -> (u8, u8, u8) {
-        match color_id {
-            0 => (255, 255, 255), // White
-            1 => (192, 192, 192), // Light gray
-            2 => (96, 96, 96),    // Dark gray
-            3 => (0, 0, 0),       // Black
-            _ => (255, 255, 255), // Default to white
+    // Player 1 uses the arrow keys for the D-pad, X and Z for A and B, Enter
+    // for Start and right Shift for Select. Player 2, when there is one, uses
+    // WASD, G and F, Space and left Shift.
+    fn button(keycode: Keycode) -> Option<(usize, Button)> {
+        match keycode {
+            Keycode::Right => Some((0, Button::Right)),
+            Keycode::Left => Some((0, Button::Left)),
+            Keycode::Up => Some((0, Button::Up)),
+            Keycode::Down => Some((0, Button::Down)),
+            Keycode::X => Some((0, Button::A)),
+            Keycode::Z => Some((0, Button::B)),
+            Keycode::RShift => Some((0, Button::Select)),
+            Keycode::Return => Some((0, Button::Start)),
+            Keycode::D => Some((1, Button::Right)),
+            Keycode::A => Some((1, Button::Left)),
+            Keycode::W => Some((1, Button::Up)),
+            Keycode::S => Some((1, Button::Down)),
+            Keycode::G => Some((1, Button::A)),
+            Keycode::F => Some((1, Button::B)),
+            Keycode::LShift => Some((1, Button::Select)),
+            Keycode::Space => Some((1, Button::Start)),
+            _ => None,
         }
     }
 
-    // Render a single tile at the given position in the framebuffer
-    pub fn render_tile(&mut self, tile_data: [u8; 16], x: u32, y: u32) {
-        for ty in 0..8 {
-            let byte1 = tile_data[ty * 2];
-            let byte2 = tile_data[ty * 2 + 1];
-            for tx in 0..8 {
-                let color_bit = 7 - tx;
-                let color_id = ((byte1 >> color_bit) & 1) | (((byte2 >> color_bit) & 1) << 1);
-                let (r, g, b) = self.get_color_from_palette(color_id);
-                self.set_pixel(x + tx as u32, y + ty as u32, r, g, b);
-            }
-        }
-    }
-
-    // Render the tile map to the screen (for now, render the first 20x18 tiles from the tile map)
-    pub fn render_tile_map(&mut self, mmu: &crate::mmu::MMU) {
-        for tile_y in 0..18 {
-            for tile_x in 0..20 {
-                let tile_index = mmu.get_tile_index_from_map(tile_x, tile_y); // Fetch the tile index from the map
-                let tile_data = mmu.get_tile_data(tile_index); // Fetch the tile data using the tile index
-
-                // Print which tile index is being rendered
-                // println!(
-                //     "Rendering tile at position ({}, {}): Tile Index = {}",
-                //     tile_x, tile_y, tile_index
-                // );
-
-                self.render_tile(tile_data, (tile_x * 8) as u32, (tile_y * 8) as u32);
-            }
-        }
+    // F1-F10 select save state slots 1-10
+    fn state_slot(keycode: Keycode) -> Option<u8> {
+        let keys = [
+            Keycode::F1,
+            Keycode::F2,
+            Keycode::F3,
+            Keycode::F4,
+            Keycode::F5,
+            Keycode::F6,
+            Keycode::F7,
+            Keycode::F8,
+            Keycode::F9,
+            Keycode::F10,
+        ];
+        keys.iter()
+            .position(|&key| key == keycode)
+            .map(|i| i as u8 + 1)
     }
 
     // Fetch tile index from the background map
-    fn fetch_tile_index(&self, tile_x: u32, tile_y: u32, mmu: &crate::mmu::MMU) -> u8 {
+    fn fetch_tile_index(&self, tile_x: u32, tile_y: u32, mmu: &rustboy::mmu::MMU) -> u8 {
         let tile_map_base = 0x9800; // Example address for background map
         let map_x = tile_x as usize;
         let map_y = tile_y as usize;
@@ -151,7 +339,7 @@ impl Graphics {
     }
 
     // Fetch tile data from VRAM based on the tile index
-    fn fetch_tile_data(&self, tile_index: u8, mmu: &crate::mmu::MMU) -> [u8; 16] {
+    fn fetch_tile_data(&self, tile_index: u8, mmu: &rustboy::mmu::MMU) -> [u8; 16] {
         let tile_data_base = 0x8000; // Example address for tile data
         let tile_address = tile_data_base + (tile_index as u16) * 16;
 
@@ -0,0 +1,119 @@
+use rustboy::audio::CYCLES_PER_FRAME;
+use rustboy::gameboy::GameBoy;
+
+use crate::debugger::{Debugging, Resume};
+use crate::options::Options;
+
+// LD B,B does nothing, so test ROMs use it as a software breakpoint
+const LD_B_B: u8 = 0x40;
+
+// When a headless run should stop, checked before every instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCondition {
+    Pc(u16), // The CPU is about to execute this address
+    LdBB,    // The next instruction is LD B,B
+}
+
+impl ExitCondition {
+    pub fn is_met(self, gameboy: &GameBoy) -> bool {
+        let pc = gameboy.cpu.registers.pc;
+        match self {
+            ExitCondition::Pc(addr) => pc == addr,
+            ExitCondition::LdBB => gameboy.mmu.peek_byte(pc) == LD_B_B,
+        }
+    }
+}
+
+impl std::str::FromStr for ExitCondition {
+    type Err = String;
+
+    // "pc=<addr>" with the address in hex, or "ld-b-b"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if s == "ld-b-b" {
+            return Ok(ExitCondition::LdBB);
+        }
+        s.strip_prefix("pc=")
+            .map(|addr| addr.trim_start_matches("0x"))
+            .and_then(|addr| u16::from_str_radix(addr, 16).ok())
+            .map(ExitCondition::Pc)
+            .ok_or_else(|| format!("Unknown exit condition: {}", s))
+    }
+}
+
+// Run with no window or audio device until a frame or cycle limit is reached
+// or the exit condition is met, and return the process exit code: 0 if the
+// run finished as asked, 1 if an emulation error stopped it, 2 if a limit
+// was hit before the exit condition
+pub fn run(options: &Options) -> i32 {
+    if options.rom_path.to_lowercase().ends_with(".gbs") {
+        eprintln!("GBS files can't be played headless");
+        return 1;
+    }
+
+    if options.dual {
+        eprintln!("Two linked Game Boys can't be run headless yet");
+        return 1;
+    }
+
+    let mut gameboy = crate::load_game(options, &options.rom_path);
+    crate::connect_link(&mut gameboy, options);
+    crate::start_audio_recording(&mut gameboy.audio, options);
+    crate::start_trace(&mut gameboy, options);
+
+    let start_cycles = gameboy.cycles;
+    let mut frames = 0;
+    crate::save_screenshot_at_frame(&gameboy, frames, options);
+
+    let mut debugging = Debugging::new(options);
+    if debugging.start(&mut gameboy) == Resume::Quit {
+        gameboy.audio.stop_recording();
+        return 0;
+    }
+
+    let code = loop {
+        let limit_code = if options.exit_on.is_some() { 2 } else { 0 };
+        if options.frames.is_some_and(|limit| frames >= limit) {
+            println!("Stopped after {} frames", frames);
+            break limit_code;
+        }
+        let cycles = gameboy.cycles - start_cycles;
+        if options.cycles.is_some_and(|limit| cycles >= limit) {
+            println!("Stopped after {} cycles", cycles);
+            break limit_code;
+        }
+
+        // The last frame is cut short to stop at the cycle limit
+        let frame_cycles = options
+            .cycles
+            .map_or(CYCLES_PER_FRAME as u64, |limit| limit - cycles)
+            .min(CYCLES_PER_FRAME as u64);
+        let result = gameboy.run_cycles_until(frame_cycles as u32, |gameboy| {
+            options
+                .exit_on
+                .is_some_and(|exit_on| exit_on.is_met(gameboy))
+        });
+        gameboy.finish_frame();
+        if result == Ok(true) {
+            println!(
+                "Exit condition met after {} frames, {} cycles",
+                frames,
+                gameboy.cycles - start_cycles
+            );
+            break 0;
+        }
+
+        // A frame an error cut short doesn't count towards the limit
+        let failed = result.is_err();
+        if !failed {
+            frames += 1;
+            crate::save_screenshot_at_frame(&gameboy, frames, options);
+        }
+        if debugging.check(&mut gameboy, result.map(|_| ())) == Resume::Quit {
+            break if failed { 1 } else { 0 };
+        }
+    };
+
+    gameboy.audio.stop_recording();
+    code
+}
@@ -1,17 +1,67 @@
+use rustboy::joypad::{Button, Buttons};
+
+// Emulator shortcuts triggered from the keyboard, as opposed to Game Boy buttons
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    ToggleAudioRecording,
+    NextTrack,
+    PreviousTrack,
+    SaveState(u8), // Slot 1-10
+    LoadState(u8),
+    Rewind, // Held
+    Turbo,  // Held
+    TogglePause,
+    FrameAdvance,
+    SpeedUp,
+    SlowDown,
+    Screenshot,
+    Debug,              // Break into the debugger
+    ToggleTileViewer,   // Open or close the VRAM tile viewer
+    ToggleMapViewer,    // Open or close the tile map viewer
+    ToggleSpriteViewer, // Open or close the OAM sprite viewer
+}
+
 pub struct Input {
-    // Fields for input handling
+    hotkeys: Vec<Hotkey>,  // Hotkeys pressed since the last poll
+    held: Vec<Hotkey>,     // Hotkeys that act for as long as they are held down
+    buttons: [Buttons; 2], // Game Boy buttons held down, per player
 }
 
 impl Input {
     pub fn new() -> Self {
-        println!("Initializing input");
         Self {
-            // Initialize fields for input
+            hotkeys: Vec::new(),
+            held: Vec::new(),
+            buttons: [Buttons::default(); 2],
+        }
+    }
+
+    pub fn press_hotkey(&mut self, hotkey: Hotkey) {
+        self.hotkeys.push(hotkey);
+    }
+
+    pub fn set_hotkey_held(&mut self, hotkey: Hotkey, held: bool) {
+        self.held.retain(|&h| h != hotkey);
+        if held {
+            self.held.push(hotkey);
         }
     }
 
-    pub fn poll(&mut self) {
-        // Placeholder for polling input
-        // println!("Polling input");
+    pub fn is_held(&self, hotkey: Hotkey) -> bool {
+        self.held.contains(&hotkey)
+    }
+
+    // `player` is 0 for the first player and 1 for the second
+    pub fn set_button(&mut self, player: usize, button: Button, pressed: bool) {
+        self.buttons[player].set(button, pressed);
+    }
+
+    pub fn buttons(&self, player: usize) -> Buttons {
+        self.buttons[player]
+    }
+
+    // Return the hotkeys pressed since the last call
+    pub fn poll(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
     }
 }
@@ -0,0 +1,46 @@
+// The eight Game Boy buttons, numbered by their bit in `Buttons`. The
+// direction keys and the action buttons each fill one row of the P1 matrix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+// The set of buttons currently held down
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Buttons(u8);
+
+impl Buttons {
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        let bit = 1 << button as u8;
+        if pressed {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+    }
+
+    pub fn is_pressed(self, button: Button) -> bool {
+        self.0 & (1 << button as u8) != 0
+    }
+
+    // The low nibble of P1 (0xFF00) for the rows selected in `p1`. Writing 0
+    // to bit 4 selects the direction keys and to bit 5 the action buttons;
+    // pressed buttons in a selected row read as 0.
+    pub fn p1_low_nibble(self, p1: u8) -> u8 {
+        let mut pressed = 0;
+        if p1 & 0x10 == 0 {
+            pressed |= self.0 & 0x0F;
+        }
+        if p1 & 0x20 == 0 {
+            pressed |= self.0 >> 4;
+        }
+        !pressed & 0x0F
+    }
+}
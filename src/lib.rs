@@ -0,0 +1,33 @@
+// The emulator core: everything needed to run a game, with no dependency on
+// a window, audio device or input backend. `GameBoy` is the entry point;
+// frontends feed it buttons and take frames and audio samples out of it.
+pub mod audio;
+pub mod cartridge;
+pub mod cpu;
+pub mod debug;
+pub mod disasm;
+pub mod error;
+pub mod gameboy;
+pub mod gbs;
+pub mod gdb;
+pub mod joypad;
+pub mod local_link;
+pub mod mmu;
+pub mod model;
+pub mod oam;
+pub mod png;
+pub mod ppu;
+pub mod printer;
+pub mod rewind;
+pub mod savestate;
+pub mod serial;
+pub mod symbols;
+pub mod tcp_link;
+pub mod trace;
+pub mod vram;
+pub mod wav;
+
+pub use error::{EmuError, RomLoadError, SaveError};
+pub use gameboy::{Config, GameBoy};
+pub use joypad::{Button, Buttons};
+pub use model::Model;
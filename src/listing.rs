@@ -0,0 +1,162 @@
+use rustboy::disasm::{self, Instruction};
+use rustboy::symbols::Symbols;
+
+const USAGE: &str = "Usage: rustboy disasm <rom> [options]
+
+Options:
+  --bank <n>     ROM bank to list from, for addresses 0x4000-0x7FFF (default 1)
+  --from <addr>  Address or label to start at (default 0x100)
+  --to <addr>    Stop before this address or label
+  --count <n>    Instructions to list, if --to isn't given (default 32)";
+
+// One line of a listing: bank and address, the raw bytes, then the mnemonic
+// with `operand_label` standing in for the address operand if given
+pub fn format_line(
+    bank: u16,
+    addr: u16,
+    instruction: &Instruction,
+    operand_label: Option<&str>,
+) -> String {
+    let bytes: Vec<String> = instruction
+        .bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    format!(
+        "{:02X}:{:04X}  {:<8}  {}",
+        bank,
+        addr,
+        bytes.join(" "),
+        instruction.labelled(operand_label)
+    )
+}
+
+// `rustboy disasm`: list the instructions in a stretch of a ROM file and
+// return the process exit code
+pub fn run(args: &[String]) -> i32 {
+    match list(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            1
+        }
+    }
+}
+
+fn list(args: &[String]) -> Result<(), String> {
+    let mut rom_path = None;
+    let mut bank = None;
+    let mut from = None;
+    let mut to = None;
+    let mut count = 32;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--bank" => bank = Some(parse_number(value()?)?),
+            "--from" => from = Some(value()?),
+            "--to" => to = Some(value()?),
+            "--count" => count = parse_number(value()?)?,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => rom_path = Some(arg),
+        }
+    }
+    let rom_path = rom_path.ok_or("Please provide a ROM file.")?;
+    let rom = std::fs::read(rom_path).map_err(|e| format!("Failed to read {}: {}", rom_path, e))?;
+    let symbols = Symbols::for_rom(rom_path).map_or_else(Symbols::default, |(_, symbols)| symbols);
+
+    // A label picks the bank as well, unless --bank overrides it
+    let from = match from
+        .map(|from| parse_location(&symbols, from))
+        .transpose()?
+    {
+        Some((label_bank, from)) => {
+            bank = bank.or(label_bank);
+            from
+        }
+        None => 0x100,
+    };
+    let to = to
+        .map(|to| parse_location(&symbols, to).map(|(_, to)| to))
+        .transpose()?;
+
+    // Bank 0 is always at 0x0000-0x3FFF and the switchable bank at 0x4000-0x7FFF
+    if from >= 0x8000 {
+        return Err(format!("{:04X} isn't in ROM", from));
+    }
+    let (bank, window_end) = if from < 0x4000 {
+        if bank.is_some_and(|bank| bank != 0) {
+            return Err("Only bank 0 is mapped at 0x0000-0x3FFF".to_string());
+        }
+        (0, 0x4000)
+    } else {
+        (bank.unwrap_or(1), 0x8000)
+    };
+    let bank_start = bank * 0x4000;
+    if bank_start >= rom.len() {
+        return Err(format!(
+            "The ROM only has {} banks",
+            rom.len().div_ceil(0x4000)
+        ));
+    }
+    let window_start = window_end - 0x4000;
+    let end = to.map_or(window_end, |to| to.min(window_end));
+    let limit = (bank_start + end)
+        .saturating_sub(window_start)
+        .min(rom.len());
+
+    let mut addr = from;
+    let mut listed = 0;
+    while to.is_some() || listed < count {
+        let offset = bank_start + addr - window_start;
+        if offset >= limit {
+            break;
+        }
+        // Operands cut off by the end of the listing read as 0
+        let instruction = disasm::disassemble(&rom[offset..limit], addr as u16);
+        if let Some(label) = symbols.label(bank as u16, addr as u16) {
+            println!("{}:", label);
+        }
+        let operand = instruction
+            .target
+            .and_then(|target| symbols.label(target_bank(bank as u16, target), target));
+        println!(
+            "{}",
+            format_line(bank as u16, addr as u16, &instruction, operand)
+        );
+        addr += instruction.length() as usize;
+        listed += 1;
+    }
+    Ok(())
+}
+
+// The bank a listing of `bank` would see mapped at `target`. WRAM banks
+// can't be known, so the first switchable one is assumed.
+fn target_bank(bank: u16, target: u16) -> u16 {
+    match target {
+        0x4000..=0x7FFF => bank.max(1),
+        0xD000..=0xDFFF => 1,
+        _ => 0,
+    }
+}
+
+// A label, giving its bank too, or a number
+fn parse_location(symbols: &Symbols, value: &str) -> Result<(Option<usize>, usize), String> {
+    match symbols.address(value) {
+        Some((bank, addr)) => Ok((Some(bank as usize), addr as usize)),
+        None => Ok((None, parse_number(value)?)),
+    }
+}
+
+// Decimal, or hex with a 0x prefix
+fn parse_number(value: &str) -> Result<usize, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number: {}", value))
+}
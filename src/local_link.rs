@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::audio::CYCLES_PER_FRAME;
+use crate::error::EmuError;
+use crate::gameboy::{GameBoy, SkippedErrors};
+use crate::serial::LinkDevice;
+
+// The state of a link cable between two machines in the same process
+#[derive(Default)]
+struct Cable {
+    waiting: [Option<u8>; 2], // SB of each end while it waits on the external clock
+    delivered: [Option<u8>; 2], // Byte clocked into each end, picked up on its next check
+}
+
+// One end of an in-process link cable. A transfer clocked from one end only
+// reaches the other if it was waiting on the external clock as of its last
+// step; otherwise the line reads 0xFF, as it would with nothing listening.
+pub struct LocalLink {
+    cable: Rc<RefCell<Cable>>,
+    end: usize,
+}
+
+impl LocalLink {
+    // Both ends of a new cable
+    pub fn cable() -> (LocalLink, LocalLink) {
+        let cable = Rc::new(RefCell::new(Cable::default()));
+        (
+            LocalLink {
+                cable: cable.clone(),
+                end: 0,
+            },
+            LocalLink { cable, end: 1 },
+        )
+    }
+}
+
+impl LinkDevice for LocalLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut cable = self.cable.borrow_mut();
+        let other = 1 - self.end;
+        match cable.waiting[other].take() {
+            Some(received) => {
+                cable.delivered[other] = Some(byte);
+                received
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn external_transfer(&mut self, byte: u8) -> Option<u8> {
+        let mut cable = self.cable.borrow_mut();
+        let received = cable.delivered[self.end].take();
+        if received.is_none() {
+            cable.waiting[self.end] = Some(byte);
+        }
+        received
+    }
+
+    // Called every step before the external clock is checked, so `waiting`
+    // only holds while this end is still waiting
+    fn tick(&mut self, _cycles: u32) {
+        self.cable.borrow_mut().waiting[self.end] = None;
+    }
+}
+
+// Two machines wired together through their link ports and run in lockstep:
+// whichever is behind in clock cycles always runs next, so a run is exactly
+// repeatable
+pub struct LinkedPair {
+    pub machines: [GameBoy; 2],
+    cycles: [u64; 2],
+}
+
+impl LinkedPair {
+    pub fn new(mut first: GameBoy, mut second: GameBoy) -> Self {
+        let (a, b) = LocalLink::cable();
+        first.set_link_device(Box::new(a));
+        second.set_link_device(Box::new(b));
+        Self {
+            machines: [first, second],
+            cycles: [0; 2],
+        }
+    }
+
+    // The machine that runs next: whichever is behind, the first on a tie
+    fn next(&self) -> usize {
+        if self.cycles[1] < self.cycles[0] {
+            1
+        } else {
+            0
+        }
+    }
+
+    // Execute one instruction on whichever machine is behind, the first on a tie
+    pub fn step(&mut self) -> Result<(), EmuError> {
+        let i = self.next();
+        // A failed instruction still takes its time
+        let before = self.machines[i].cycles;
+        let result = self.machines[i].step();
+        self.cycles[i] += self.machines[i].cycles - before;
+        result.map(|_| ())
+    }
+
+    // Run both machines a frame past whichever is ahead, then finish the
+    // frame on both. Like `GameBoy::run_frame`, a breakpoint or watchpoint
+    // cuts the frame short, as does an error unless the machine that ran
+    // into it carries on past errors. The frame is still finished.
+    pub fn run_frame(&mut self) -> Result<(), EmuError> {
+        let target = self.cycles[0].max(self.cycles[1]) + CYCLES_PER_FRAME as u64;
+        let mut skipped = SkippedErrors::default();
+        let mut result = Ok(());
+        while self.cycles.iter().any(|&cycles| cycles < target)
+            && !self.machines.iter().any(GameBoy::debug_stop_pending)
+        {
+            let continue_on_error = self.machines[self.next()].continue_on_error;
+            match self.step() {
+                Err(error) if continue_on_error => skipped.skip(error),
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+                Ok(()) => {}
+            }
+        }
+        skipped.finish();
+        for machine in &mut self.machines {
+            machine.finish_frame();
+        }
+        result
+    }
+}
@@ -0,0 +1,165 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+// Where the log filter comes from when --log isn't given
+const ENV_VAR: &str = "RUSTBOY_LOG";
+
+// Which log messages to show: a level for everything, then levels for
+// targets that override it, like "info,mmu=trace,cpu=off". The emulator
+// core logs to the targets cpu, mmu, ppu, apu, mbc and serial; other
+// messages go to the module they come from, like gdb or rewind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Info,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl std::str::FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = Self::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let level = |level: &str| {
+                level
+                    .parse()
+                    .map_err(|_| format!("Unknown log level: {}", level))
+            };
+            match part.split_once('=') {
+                Some(("", _)) => return Err(format!("Missing log target in {}", part)),
+                Some((target, level_name)) => filter
+                    .targets
+                    .push((target.to_string(), level(level_name)?)),
+                None => filter.default = level(part)?,
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl LogFilter {
+    // The last matching target wins. Module paths match by their last part,
+    // so "gdb" covers rustboy::gdb.
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let name = target.rsplit("::").next().unwrap_or(target);
+        self.targets
+            .iter()
+            .rev()
+            .find(|(t, _)| t == target || t == name)
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    // The most verbose level anything is logged at
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+struct Logger {
+    filter: LogFilter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let target = record.target();
+        let target = target.rsplit("::").next().unwrap_or(target);
+        eprintln!("[{} {}] {}", record.level(), target, record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+// Send log messages to stderr, filtered by `filter` or else the RUSTBOY_LOG
+// environment variable, showing info and above by default
+pub fn init(filter: Option<&LogFilter>) {
+    let filter = filter.cloned().unwrap_or_else(|| {
+        std::env::var(ENV_VAR)
+            .ok()
+            .and_then(|spec| {
+                spec.parse()
+                    .inspect_err(|e| eprintln!("Ignoring {}: {}", ENV_VAR, e))
+                    .ok()
+            })
+            .unwrap_or_default()
+    });
+    log::set_max_level(filter.max_level());
+    let _ = log::set_boxed_logger(Box::new(Logger { filter }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<LogFilter, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn targets_override_the_default() {
+        let filter = parse("info,mmu=trace,cpu=off").unwrap();
+        assert_eq!(filter.level_for("mmu"), LevelFilter::Trace);
+        assert_eq!(filter.level_for("cpu"), LevelFilter::Off);
+        assert_eq!(filter.level_for("ppu"), LevelFilter::Info);
+        assert_eq!(filter.level_for("rustboy::gdb"), LevelFilter::Info);
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+
+        // The last mention of a target wins
+        let filter = parse("mmu=trace,mmu=warn").unwrap();
+        assert_eq!(filter.level_for("mmu"), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn module_paths_match_by_their_last_part() {
+        let filter = parse("warn,gdb=debug").unwrap();
+        assert_eq!(filter.level_for("rustboy::gdb"), LevelFilter::Debug);
+        assert_eq!(filter.level_for("rustboy::rewind"), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn unknown_targets_are_kept_but_match_nothing_else() {
+        // Any module can log, so targets aren't checked against a list
+        let filter = parse("error,nonsense=trace").unwrap();
+        assert_eq!(filter.level_for("nonsense"), LevelFilter::Trace);
+        assert_eq!(filter.level_for("cpu"), LevelFilter::Error);
+    }
+
+    #[test]
+    fn unknown_levels_are_rejected() {
+        assert_eq!(parse("loud"), Err("Unknown log level: loud".to_string()));
+        assert_eq!(
+            parse("info,mmu=loud"),
+            Err("Unknown log level: loud".to_string())
+        );
+        assert_eq!(parse("mmu="), Err("Unknown log level: ".to_string()));
+        assert_eq!(
+            parse("=trace"),
+            Err("Missing log target in =trace".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_entries_are_skipped() {
+        assert_eq!(parse(""), Ok(LogFilter::default()));
+        let filter = parse(" , debug,, mmu=trace ,").unwrap();
+        assert_eq!(filter.level_for("cpu"), LevelFilter::Debug);
+        assert_eq!(filter.level_for("mmu"), LevelFilter::Trace);
+    }
+}
@@ -1,49 +1,186 @@
-mod audio;
-mod cpu;
+mod debugger;
+mod headless;
+mod listing;
+mod logging;
+mod options;
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+mod speed;
+
+#[cfg(feature = "sdl")]
 mod graphics;
+#[cfg(feature = "sdl")]
 mod input;
-mod mmu;
+#[cfg(feature = "sdl")]
+mod viewer;
+#[cfg(feature = "sdl")]
+mod window;
+
+#[cfg(feature = "sdl")]
+use rustboy::local_link::LinkedPair;
+use rustboy::printer::Printer;
+use rustboy::symbols::Symbols;
+use rustboy::tcp_link::TcpLink;
+use rustboy::{audio, cartridge, gameboy, serial, RomLoadError};
 
 fn main() {
-    // Initialize components
-    let mut cpu = cpu::CPU::new();
-    let mut mmu = mmu::MMU::new();
-    let mut graphics = graphics::Graphics::new();
-    let mut input = input::Input::new();
-    let mut audio = audio::Audio::new();
-
-    // Load the cartridge into the emulator
-    let rom_path = std::env::args().nth(1).expect("Please provide a ROM file.");
-    mmu.load_rom(rom_path);
-
-    let frame_duration = std::time::Duration::from_millis(16); // Roughly 60 FPS
-
-    // Main emulation loop
-    loop {
-        let frame_start = std::time::Instant::now();
-
-        // Handle events (quit if needed)
-        if graphics.handle_events() {
-            break; // Exit the loop if the user closes the window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "disasm") {
+        std::process::exit(listing::run(&args[1..]));
+    }
+    let options = options::Options::parse_from(args);
+    logging::init(options.log.as_ref());
+
+    if options.headless {
+        std::process::exit(headless::run(&options));
+    }
+    run_windowed(&options);
+}
+
+#[cfg(feature = "sdl")]
+fn run_windowed(options: &options::Options) {
+    if options.rom_path.to_lowercase().ends_with(".gbs") {
+        window::run_gbs_player(options);
+    } else if options.dual {
+        let second_rom = options.dual_rom.as_ref().unwrap_or(&options.rom_path);
+        let pair = LinkedPair::new(
+            load_game(options, &options.rom_path),
+            load_game(options, second_rom),
+        );
+        window::run_dual(pair, options);
+    } else {
+        let mut gameboy = load_game(options, &options.rom_path);
+        connect_link(&mut gameboy, options);
+        window::run(gameboy, options);
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_windowed(_options: &options::Options) {
+    eprintln!("rustboy was built without the sdl feature, so only --headless is available");
+    std::process::exit(1);
+}
+
+// Load a ROM and set up the machine the options ask for
+fn load_game(options: &options::Options, rom_path: &str) -> gameboy::GameBoy {
+    let rom = cartridge::read_rom(rom_path).unwrap_or_else(|e| exit_with_error(&e));
+    let mut config = gameboy::Config {
+        model: options.model,
+        boot_rom: options.boot_rom.as_ref().and_then(|path| {
+            std::fs::read(path)
+                .inspect_err(|e| println!("Failed to read boot ROM {}, skipping it: {}", path, e))
+                .ok()
+        }),
+    };
+
+    let mut gameboy = match gameboy::GameBoy::new(&rom, config.clone()) {
+        Ok(gameboy) => gameboy,
+        Err(e @ RomLoadError::BootRomSize(_)) => {
+            println!("Failed to load boot ROM, skipping it: {}", e);
+            config.boot_rom = None;
+            gameboy::GameBoy::new(&rom, config).unwrap_or_else(|e| exit_with_error(&e))
         }
+        Err(e) => exit_with_error(&e),
+    };
 
-        // Execute CPU cycles
-        cpu.step(&mut mmu);
+    let model = gameboy.model;
+    let mode = if model.is_cgb() && !gameboy.mmu.cgb_mode {
+        " in compatibility mode"
+    } else {
+        ""
+    };
+    println!(
+        "Emulating {:?}{} for \"{}\"",
+        model, mode, gameboy.header.title
+    );
 
-        // Render the tile map from VRAM
-        graphics.render_tile_map(&mmu);
+    gameboy.continue_on_error = options.on_error == debugger::ErrorPolicy::Continue;
 
-        // Render the graphics to the screen
-        graphics.render();
+    if let Some((path, symbols)) = Symbols::for_rom(rom_path) {
+        println!("Loaded {} symbols from {}", symbols.len(), path);
+        gameboy.symbols = symbols;
+    }
 
-        // Update audio and input
-        audio.update();
-        input.poll();
+    gameboy
+}
 
-        // Limit frame rate to ~60 FPS
-        let frame_time = frame_start.elapsed();
-        if frame_time < frame_duration {
-            std::thread::sleep(frame_duration - frame_time);
+fn exit_with_error(error: &RomLoadError) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
+// Plug in the link port device the options ask for
+fn connect_link(gameboy: &mut gameboy::GameBoy, options: &options::Options) {
+    match &options.link {
+        options::Link::Disconnected => {}
+        options::Link::Stdout => gameboy.set_link_device(Box::new(serial::StdoutLogger)),
+        options::Link::Printer => {
+            let printer = Printer::new(&timestamped_path("png"));
+            gameboy.set_link_device(Box::new(printer))
         }
+        options::Link::Listen(address) => match TcpLink::listen(address) {
+            Ok(link) => gameboy.set_link_device(Box::new(link)),
+            Err(e) => println!("Failed to listen for a link on {}: {}", address, e),
+        },
+        options::Link::Connect(address) => match TcpLink::connect(address) {
+            Ok(link) => gameboy.set_link_device(Box::new(link)),
+            Err(e) => println!("Failed to connect the link to {}: {}", address, e),
+        },
     }
 }
+
+// Save a screenshot at native size, plus a scaled copy next to it as
+// `<stem>_<n>x.png` if --screenshot-scale asks for one
+fn save_screenshot(gameboy: &gameboy::GameBoy, path: &str, options: &options::Options) {
+    let mut outputs = vec![(path.to_string(), 1)];
+    if options.screenshot_scale > 1 {
+        let stem = path.strip_suffix(".png").unwrap_or(path);
+        let scaled_path = format!("{}_{}x.png", stem, options.screenshot_scale);
+        outputs.push((scaled_path, options.screenshot_scale));
+    }
+
+    for (path, scale) in outputs {
+        match gameboy.save_screenshot(&path, scale) {
+            Ok(()) => println!("Saved screenshot to {}", path),
+            Err(e) => println!("Failed to save screenshot to {}: {}", path, e),
+        }
+    }
+}
+
+// Take the --screenshot-at-frame screenshot if `frames` frames have just run
+fn save_screenshot_at_frame(gameboy: &gameboy::GameBoy, frames: u64, options: &options::Options) {
+    if let Some((frame, path)) = &options.screenshot_at {
+        if *frame == frames {
+            save_screenshot(gameboy, path, options);
+        }
+    }
+}
+
+fn start_audio_recording(audio: &mut audio::Audio, options: &options::Options) {
+    if let Some(path) = &options.record_audio {
+        if let Err(e) = audio.start_recording(path, options.record_channels) {
+            println!("Failed to start audio recording to {}: {}", path, e);
+        }
+    }
+}
+
+// Set up instruction tracing as the options ask
+fn start_trace(gameboy: &mut gameboy::GameBoy, options: &options::Options) {
+    let trace = &mut gameboy.cpu.trace;
+    trace.range = options.trace_range;
+    trace.bank = options.trace_bank;
+    trace.keep_history(options.trace_last);
+    if let Some(path) = &options.trace {
+        if let Err(e) = trace.log_to_file(path) {
+            println!("Failed to start tracing to {}: {}", path, e);
+        }
+    }
+}
+
+// A file name like rustboy_1700000000.wav, unique to the second
+fn timestamped_path(extension: &str) -> String {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("rustboy_{}.{}", timestamp, extension)
+}
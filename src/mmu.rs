@@ -1,78 +1,399 @@
+use crate::cartridge::Header;
+use crate::debug::Watchpoints;
+use crate::error::RomLoadError;
+use crate::joypad::Buttons;
+use crate::model::Model;
+use crate::savestate::{StateReader, StateWriter};
+use crate::serial::Serial;
+use crate::vram::TILES_PER_BANK;
+
+// The (R) symbol the DMG boot ROM draws next to the logo
+const REGISTERED_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+// IO registers as the DMG boot ROM leaves them. Other models differ in a few
+// of these, patched up in `apply_post_boot_state`.
+const POST_BOOT_IO: [(u16, u8); 48] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF04, 0xAB), // DIV
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x85), // STAT
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF44, 0x00), // LY
+    (0xFF45, 0x00), // LYC
+    (0xFF46, 0xFF), // DMA
+    (0xFF47, 0xFC), // BGP
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+    (0xFF4D, 0xFF), // KEY1
+    (0xFF4F, 0xFF), // VBK
+    (0xFF51, 0xFF), // HDMA1
+    (0xFF52, 0xFF), // HDMA2
+    (0xFF53, 0xFF), // HDMA3
+    (0xFF54, 0xFF), // HDMA4
+    (0xFF55, 0xFF), // HDMA5
+    (0xFF70, 0xFF), // SVBK
+    (0xFFFF, 0x00), // IE
+];
+
+// A ROM bigger than the 32KB window, switched in 16KB banks at 0x4000 by
+// writing the bank number to 0x2000-0x3FFF like on MBC1. Only GBS rips are
+// mapped this way so far; cartridges are still copied flat into memory.
+struct BankedRom {
+    data: Vec<u8>, // Padded to whole banks
+    bank: usize,   // Mapped at 0x4000
+}
+
 pub struct MMU {
-    pub memory: [u8; 0x10000], // 64KB memory
+    pub memory: [u8; 0x10000],     // 64KB memory
+    boot_rom: Option<Vec<u8>>,     // Mapped over the cartridge until 0xFF50 is written
+    banked_rom: Option<BankedRom>, // Replaces 0x0000-0x7FFF of memory when set
+    pub model: Model,              // Hardware being emulated
+    pub cgb_mode: bool,            // CGB features enabled; false for monochrome games on a CGB
+    pub vram_bank1: Vec<u8>,       // CGB second VRAM bank, selected through VBK (0xFF4F)
+    pub wram_banks: Vec<u8>,       // CGB WRAM banks 2-7, mapped at 0xD000 through SVBK (0xFF70)
+    pub buttons: Buttons,          // Held buttons, read back through P1 (0xFF00)
+    pub serial: Serial,            // Link port transfers through SB (0xFF01) and SC (0xFF02)
+    pub watchpoints: Watchpoints,  // Checked on every read and write; not part of save states
 }
 
 impl MMU {
-    pub fn new() -> Self {
+    pub fn new(model: Model) -> Self {
         Self {
             memory: [0; 0x10000], // Initialize memory to 0
+            boot_rom: None,
+            banked_rom: None,
+            model,
+            cgb_mode: false,
+            vram_bank1: vec![0; 0x2000],
+            wram_banks: vec![0; 6 * 0x1000],
+            buttons: Buttons::default(),
+            serial: Serial::new(),
+            watchpoints: Watchpoints::default(),
+        }
+    }
+
+    pub fn header(&self) -> Header {
+        Header::parse(&self.memory[..0x150])
+    }
+
+    fn vram_bank(&self) -> u8 {
+        if self.cgb_mode {
+            self.memory[0xFF4F] & 0x01
+        } else {
+            0
         }
     }
 
-    pub fn load_rom(&mut self, path: String) {
-        let rom_data = std::fs::read(path).expect("Failed to read ROM file");
+    // WRAM bank mapped at 0xD000; writing 0 to SVBK selects bank 1
+    fn wram_bank(&self) -> u8 {
+        if self.cgb_mode {
+            (self.memory[0xFF70] & 0x07).max(1)
+        } else {
+            1
+        }
+    }
+
+    // Map a boot ROM over the start of the cartridge. 256-byte images cover
+    // 0x0000-0x00FF; 2304-byte CGB images also cover 0x0200-0x08FF.
+    pub fn load_boot_rom(&mut self, data: Vec<u8>) -> Result<(), RomLoadError> {
+        if data.len() != 0x100 && data.len() != 0x900 {
+            return Err(RomLoadError::BootRomSize(data.len()));
+        }
+        self.boot_rom = Some(data);
+        Ok(())
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    // Initialize IO registers and VRAM to what the model's boot ROM leaves behind
+    pub fn apply_post_boot_state(&mut self) {
+        let model = self.model;
+        for &(addr, value) in POST_BOOT_IO.iter() {
+            self.memory[addr as usize] = value;
+        }
+
+        if model == Model::Dmg0 {
+            self.memory[0xFF04] = 0x18; // DIV
+            self.memory[0xFF41] = 0x81; // STAT
+        }
+        if model.is_sgb() {
+            self.memory[0xFF26] = 0xF0; // NR52
+        }
+        if model.is_cgb() {
+            self.memory[0xFF02] = 0x7F; // SC
+            self.memory[0xFF46] = 0x00; // DMA
+            self.memory[0xFF4D] = 0x7E; // KEY1
+            self.memory[0xFF4F] = 0xFE; // VBK
+            self.memory[0xFF70] = 0xF8; // SVBK
+        }
+
+        // The monochrome boot ROMs leave the scrolled-in logo in VRAM
+        if !model.is_cgb() {
+            self.load_logo_tiles();
+        }
+    }
+
+    // Recreate the boot ROM's logo: each nibble of the header logo at
+    // 0x104-0x133 becomes two rows of a tile, with every pixel doubled
+    fn load_logo_tiles(&mut self) {
+        let mut addr = 0x8010;
+        for i in 0..48 {
+            let logo_byte = self.memory[0x104 + i];
+            for nibble in [logo_byte >> 4, logo_byte & 0x0F] {
+                let mut doubled = 0u8;
+                for bit in 0..4 {
+                    if nibble & (1 << bit) != 0 {
+                        doubled |= 0b11 << (bit * 2);
+                    }
+                }
+                self.memory[addr] = doubled;
+                self.memory[addr + 2] = doubled;
+                addr += 4;
+            }
+        }
+
+        for (i, byte) in REGISTERED_TILE.iter().enumerate() {
+            self.memory[0x8190 + i * 2] = *byte;
+        }
+
+        // Tiles 1-12 on the first row of the logo, 13-24 on the second, then (R)
+        for i in 0..12 {
+            self.memory[0x9904 + i] = i as u8 + 1;
+            self.memory[0x9924 + i] = i as u8 + 13;
+        }
+        self.memory[0x9910] = 0x19;
+    }
+
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), RomLoadError> {
         let memory_size = self.memory.len();
         let rom_size = rom_data.len();
+        if rom_size < 0x150 {
+            return Err(RomLoadError::TooSmall(rom_size));
+        }
 
         for (i, byte) in rom_data.iter().enumerate() {
             if i < memory_size {
                 self.memory[i] = *byte;
             } else {
-                println!("Warning: ROM size exceeds memory limit, truncating...");
+                log::warn!(target: "mbc", "ROM size exceeds memory limit, truncating...");
                 break;
             }
         }
 
-        println!("ROM loaded, size: {} bytes", rom_size);
+        log::info!(target: "mbc", "ROM loaded, size: {} bytes", rom_size);
+
+        // A CGB only enables its color features for games that ask for them.
+        // With a boot ROM, it makes that decision itself through KEY0.
+        self.cgb_mode = self.model.is_cgb() && self.header().supports_cgb();
+        Ok(())
     }
 
+    // Map a ROM image at 0x0000 with bank 1 at 0x4000. Writes to the ROM area
+    // no longer reach memory; those to 0x2000-0x3FFF select the bank.
+    pub fn map_banked_rom(&mut self, mut data: Vec<u8>) {
+        let banks = data.len().div_ceil(0x4000).max(2);
+        data.resize(banks * 0x4000, 0);
+        self.banked_rom = Some(BankedRom { data, bank: 1 });
+    }
+
+    // A read by the CPU, which watchpoints see
     pub fn read_byte(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
+        let value = self.peek_byte(addr);
+        self.watchpoints.check(addr, value, false);
+        value
+    }
+
+    // Read without side effects, for the debugger and the rest of the hardware
+    pub fn peek_byte(&self, addr: u16) -> u8 {
+        if let Some(boot_rom) = &self.boot_rom {
+            let addr = addr as usize;
+            if addr < 0x100 || ((0x200..0x900).contains(&addr) && addr < boot_rom.len()) {
+                return boot_rom[addr];
+            }
+        }
+        if let Some(rom) = self.banked_rom.as_ref().filter(|_| addr < 0x8000) {
+            return match addr {
+                0x0000..=0x3FFF => rom.data[addr as usize],
+                _ => rom.data[rom.bank * 0x4000 + (addr as usize - 0x4000)],
+            };
+        }
+
+        match addr {
+            0x8000..=0x9FFF if self.vram_bank() == 1 => self.vram_bank1[addr as usize - 0x8000],
+            0xD000..=0xDFFF if self.wram_bank() > 1 => self.wram_banks[self.wram_bank_offset(addr)],
+            // CGB-only registers read as 0xFF on monochrome hardware and in compatibility mode
+            0xFF4D | 0xFF4F | 0xFF51..=0xFF55 | 0xFF68..=0xFF6B | 0xFF70 if !self.cgb_mode => 0xFF,
+            0xFF4F => 0xFE | self.memory[0xFF4F],
+            0xFF70 => 0xF8 | self.memory[0xFF70],
+            0xFF00 => {
+                let p1 = self.memory[0xFF00];
+                0xC0 | (p1 & 0x30) | self.buttons.p1_low_nibble(p1)
+            }
+            _ => self.memory[addr as usize],
+        }
+    }
+
+    fn wram_bank_offset(&self, addr: u16) -> usize {
+        (self.wram_bank() as usize - 2) * 0x1000 + (addr as usize - 0xD000)
+    }
+
+    // Which bank is mapped at `addr`: the ROM bank at 0x4000-0x7FFF, the
+    // VRAM bank at 0x8000-0x9FFF, the WRAM bank at 0xD000-0xDFFF, otherwise 0
+    pub fn bank_at(&self, addr: u16) -> u16 {
+        match addr {
+            0x4000..=0x7FFF => self.banked_rom.as_ref().map_or(1, |rom| rom.bank as u16),
+            0x8000..=0x9FFF => self.vram_bank() as u16,
+            0xD000..=0xDFFF => self.wram_bank() as u16,
+            _ => 0,
+        }
     }
 
     pub fn write_byte(&mut self, addr: u16, value: u8) {
+        self.watchpoints.check(addr, value, true);
+
+        // Writing a non-zero value to 0xFF50 unmaps the boot ROM until the next reset
+        if addr == 0xFF50 && value != 0 && self.boot_rom.take().is_some() {
+            log::debug!(target: "mmu", "Boot ROM unmapped");
+        }
+
+        // The CGB boot ROM writes KEY0 to drop into compatibility mode for monochrome games
+        if addr == 0xFF4C && self.boot_rom.is_some() && self.model.is_cgb() {
+            self.cgb_mode = value & 0x04 == 0;
+        }
+
+        if let Some(rom) = self.banked_rom.as_mut().filter(|_| addr < 0x8000) {
+            // Bank 0 can't be mapped twice, so selecting it gives bank 1
+            if (0x2000..0x4000).contains(&addr) {
+                let banks = rom.data.len() / 0x4000;
+                rom.bank = match value as usize % banks {
+                    0 => 1,
+                    bank => bank,
+                };
+                log::trace!(target: "mbc", "ROM bank {} selected", rom.bank);
+            }
+            return;
+        }
+
+        match addr {
+            0xFF02 if value & 0x80 != 0 => {
+                log::debug!(target: "serial", "Transfer of 0x{:02X} started", self.memory[0xFF01]);
+                self.serial.start(value, self.cgb_mode);
+            }
+            0x8000..=0x9FFF if self.vram_bank() == 1 => {
+                self.vram_bank1[addr as usize - 0x8000] = value;
+                return;
+            }
+            0xD000..=0xDFFF if self.wram_bank() > 1 => {
+                let offset = self.wram_bank_offset(addr);
+                self.wram_banks[offset] = value;
+                return;
+            }
+            _ => {}
+        }
+
         self.memory[addr as usize] = value;
 
-        // Debugging write operations to the tile map region (0x9800-0x9BFF)
-        if addr >= 0x9800 && addr < 0x9C00 {
-            println!(
+        if (0x9800..0x9C00).contains(&addr) {
+            log::trace!(
+                target: "ppu",
                 "Tile Map Write: Addr = 0x{:04X}, Value = 0x{:02X}",
-                addr, value
+                addr,
+                value
             );
         }
     }
 
-    pub fn get_tile_index_from_map(&self, x: u16, y: u16) -> u16 {
-        let map_address = 0x9800 + y * 32 + x; // Each row has 32 tiles
-        let tile_index = self.read_byte(map_address) as u16;
-
-        // Debug: Print the content of the tile map from 0x9800 to 0x9BFF
-        // for addr in 0x9800..0x9C00 {
-        //     println!(
-        //         "Tile map address 0x{:04X}: 0x{:02X}",
-        //         addr, self.memory[addr as usize]
-        //     );
-        // }
+    // Advance the hardware clocked alongside the CPU by `cycles`
+    pub fn tick(&mut self, cycles: u32) {
+        let (sb, sc) = (self.memory[0xFF01], self.memory[0xFF02]);
+        if let Some(received) = self.serial.tick(sb, sc, cycles) {
+            self.memory[0xFF01] = received;
+            self.memory[0xFF02] = sc & 0x7F;
+            self.memory[0xFF0F] |= 0x08; // Serial interrupt
+        }
+    }
 
-        // println!(
-        //     "Tile Index at ({}, {}): {} from address: 0x{:04X}",
-        //     x, y, tile_index, map_address
-        // ); // Debug output to check tile indices
+    // Everything memory-mapped: RAM, VRAM, OAM and the IO registers of the PPU,
+    // timer and APU, plus the CGB banks and whether the boot ROM is still mapped.
+    // The timer doesn't count yet, so its registers are all the state it has.
+    // A banked GBS rip saves its bank number; the data comes from the file.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.memory);
+        w.write_bool(self.cgb_mode);
+        w.write_bytes(&self.vram_bank1);
+        w.write_bytes(&self.wram_banks);
+        match &self.boot_rom {
+            Some(boot_rom) => {
+                w.write_bool(true);
+                w.write_bytes(boot_rom);
+            }
+            None => w.write_bool(false),
+        }
+        w.write_u16(self.banked_rom.as_ref().map_or(0, |rom| rom.bank as u16));
+        self.serial.save_state(w);
+    }
 
-        tile_index
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.read_into(&mut self.memory)?;
+        self.cgb_mode = r.read_bool()?;
+        r.read_into(&mut self.vram_bank1)?;
+        r.read_into(&mut self.wram_banks)?;
+        self.boot_rom = if r.read_bool()? {
+            Some(r.read_bytes()?.to_vec())
+        } else {
+            None
+        };
+        let bank = r.read_u16()? as usize;
+        if let Some(rom) = &mut self.banked_rom {
+            rom.bank = bank.clamp(1, rom.data.len() / 0x4000 - 1);
+        }
+        self.serial.load_state(r)?;
+        Ok(())
     }
 
-    pub fn get_tile_data(&self, tile_index: u16) -> [u8; 16] {
-        let start_addr = 0x8000 + (tile_index * 16) as usize;
+    // The 16 bytes of tile `number` (0-383) in VRAM bank `bank`, 0 or 1,
+    // regardless of which bank VBK maps in for the CPU. Panics on any other
+    // bank or tile, which would be a bug in the caller.
+    pub fn tile_data_in_bank(&self, bank: u8, number: u16) -> [u8; 16] {
+        assert!(bank <= 1, "No VRAM bank {}", bank);
+        assert!(number < TILES_PER_BANK, "No tile {} in VRAM", number);
+        let offset = number as usize * 16;
+        let vram = match bank {
+            0 => &self.memory[0x8000..0x9800],
+            _ => &self.vram_bank1[..0x1800],
+        };
         let mut tile_data = [0u8; 16];
-        tile_data.copy_from_slice(&self.memory[start_addr..start_addr + 16]);
-
-        // Print the tile data for debugging
-        // println!(
-        //     "Tile Data for tile index {} at VRAM address 0x{:04X}: {:?}",
-        //     tile_index, start_addr, tile_data
-        // );
-
+        tile_data.copy_from_slice(&vram[offset..offset + 16]);
         tile_data
     }
 }
@@ -0,0 +1,70 @@
+use crate::cartridge::Header;
+
+// The Game Boy hardware revisions. They differ mostly in the state their boot
+// ROMs leave behind, which games occasionally use to detect the hardware.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    Dmg0, // Early original Game Boy
+    Dmg,  // Original Game Boy
+    Mgb,  // Game Boy Pocket
+    Sgb,  // Super Game Boy
+    Sgb2, // Super Game Boy 2
+    Cgb,  // Game Boy Color
+    Agb,  // Game Boy Advance
+}
+
+impl Model {
+    pub const ALL: [Model; 7] = [
+        Model::Dmg0,
+        Model::Dmg,
+        Model::Mgb,
+        Model::Sgb,
+        Model::Sgb2,
+        Model::Cgb,
+        Model::Agb,
+    ];
+
+    // Stable numbering for file formats such as save states
+    pub fn id(self) -> u8 {
+        Self::ALL.iter().position(|&m| m == self).unwrap() as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Model> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    // Pick the model a cartridge was made for: CGB-aware games get a Game Boy
+    // Color, everything else the original Game Boy
+    pub fn from_header(header: &Header) -> Model {
+        if header.supports_cgb() {
+            Model::Cgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn is_sgb(self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+}
+
+impl std::str::FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dmg0" => Ok(Model::Dmg0),
+            "dmg" => Ok(Model::Dmg),
+            "mgb" => Ok(Model::Mgb),
+            "sgb" => Ok(Model::Sgb),
+            "sgb2" => Ok(Model::Sgb2),
+            "cgb" => Ok(Model::Cgb),
+            "agb" => Ok(Model::Agb),
+            _ => Err(format!("Unknown model: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+
+use crate::mmu::MMU;
+use crate::ppu::decode_tile;
+use crate::vram::{set_pixel, TilePalette};
+
+// OAM at 0xFE00-0xFE9F holds 40 sprites of 4 bytes each
+pub const SPRITE_COUNT: u8 = 40;
+const OAM_START: usize = 0xFE00;
+
+// The PPU only draws the first 10 sprites it finds on each line
+pub const SPRITES_PER_LINE: usize = 10;
+
+// LCDC bit 2 makes every sprite two tiles tall
+const LCDC_TALL_SPRITES: u8 = 0x04;
+
+// The sprite sheet lays sprites out 8 wide and 5 high, each in a cell with
+// a border that says whether it's on the current line
+const SHEET_COLUMNS: u32 = 8;
+const SHEET_ROWS: u32 = SPRITE_COUNT as u32 / SHEET_COLUMNS;
+const BORDER: u32 = 2;
+const CELL_WIDTH: u32 = 8 + BORDER * 2;
+const CELL_HEIGHT: u32 = 16 + BORDER * 2;
+
+const TRANSPARENT_COLOR: (u8, u8, u8) = (64, 64, 64); // Color ID 0
+const EMPTY_COLOR: (u8, u8, u8) = (32, 32, 32); // Border, and below 8 pixel tall sprites
+const DRAWN_COLOR: (u8, u8, u8) = (0, 192, 0);
+const DROPPED_COLOR: (u8, u8, u8) = (255, 0, 0);
+
+// One OAM entry. X and Y are offset so 0 hides the sprite: the screen
+// position is X - 8 and Y - 16.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub number: u8, // 0-39, in OAM order
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: u8,
+}
+
+impl Sprite {
+    pub fn read(mmu: &MMU, number: u8) -> Self {
+        let start = OAM_START + number as usize * 4;
+        let [y, x, tile, flags] = std::array::from_fn(|i| mmu.memory[start + i]);
+        Self {
+            number,
+            y,
+            x,
+            tile,
+            flags,
+        }
+    }
+
+    // Every sprite in OAM order
+    pub fn all(mmu: &MMU) -> Vec<Self> {
+        (0..SPRITE_COUNT)
+            .map(|number| Self::read(mmu, number))
+            .collect()
+    }
+
+    // Drawn behind background colors 1-3
+    pub fn behind_background(self) -> bool {
+        self.flags & 0x80 != 0
+    }
+
+    pub fn y_flip(self) -> bool {
+        self.flags & 0x40 != 0
+    }
+
+    pub fn x_flip(self) -> bool {
+        self.flags & 0x20 != 0
+    }
+
+    // OBP0 or OBP1, which is all a monochrome game picks between
+    pub fn dmg_palette(self) -> TilePalette {
+        if self.flags & 0x10 != 0 {
+            TilePalette::Obp1
+        } else {
+            TilePalette::Obp0
+        }
+    }
+
+    // One of the 8 CGB object palettes
+    pub fn cgb_palette(self) -> u8 {
+        self.flags & 0x07
+    }
+
+    // Only CGB games take tiles from the second VRAM bank
+    pub fn bank(self, mmu: &MMU) -> u8 {
+        if mmu.cgb_mode {
+            (self.flags >> 3) & 1
+        } else {
+            0
+        }
+    }
+
+    // Whether the sprite covers a line of the screen, wherever it is across
+    pub fn on_line(self, line: u8, height: u8) -> bool {
+        let top = self.y as i16 - 16;
+        (top..top + height as i16).contains(&(line as i16))
+    }
+
+    // The color IDs of the sprite's pixels, 8 or 16 rows, flipped as its
+    // flags say. Tall sprites use an even tile on top and the odd one after.
+    pub fn color_ids(self, mmu: &MMU) -> Vec<[u8; 8]> {
+        let height = sprite_height(mmu);
+        let first = if height == 16 {
+            self.tile & 0xFE
+        } else {
+            self.tile
+        };
+        let bank = self.bank(mmu);
+        let mut rows: Vec<[u8; 8]> = (0..height / 8)
+            .flat_map(|i| decode_tile(&mmu.tile_data_in_bank(bank, (first + i) as u16)))
+            .collect();
+        if self.x_flip() {
+            rows.iter_mut().for_each(|row| row.reverse());
+        }
+        if self.y_flip() {
+            rows.reverse();
+        }
+        rows
+    }
+}
+
+// Sprites list as "sprite 05 at 30,30 (screen 40,32) tile 1C flags 30: OBP1, X flip"
+impl fmt::Display for Sprite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sprite {:02X} at {:02X},{:02X} (screen {},{}) tile {:02X} flags {:02X}: {}",
+            self.number,
+            self.x,
+            self.y,
+            self.x as i16 - 8,
+            self.y as i16 - 16,
+            self.tile,
+            self.flags,
+            self.dmg_palette()
+        )?;
+        if self.flags & 0x0F != 0 {
+            write!(
+                f,
+                ", CGB palette {} bank {}",
+                self.cgb_palette(),
+                (self.flags >> 3) & 1
+            )?;
+        }
+        if self.x_flip() {
+            write!(f, ", X flip")?;
+        }
+        if self.y_flip() {
+            write!(f, ", Y flip")?;
+        }
+        if self.behind_background() {
+            write!(f, ", behind BG")?;
+        }
+        Ok(())
+    }
+}
+
+// 8 pixels tall, or 16 with LCDC bit 2 set
+pub fn sprite_height(mmu: &MMU) -> u8 {
+    if mmu.memory[0xFF40] & LCDC_TALL_SPRITES != 0 {
+        16
+    } else {
+        8
+    }
+}
+
+// The sprites on one line of the screen, by OAM number. The PPU takes the
+// first 10 in OAM order whose rows cover the line, even ones off the side
+// of the screen, and drops the rest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineSprites {
+    pub drawn: Vec<u8>,
+    pub dropped: Vec<u8>,
+}
+
+impl LineSprites {
+    pub fn new(mmu: &MMU, line: u8) -> Self {
+        let height = sprite_height(mmu);
+        let mut sprites = Self::default();
+        for sprite in Sprite::all(mmu)
+            .into_iter()
+            .filter(|s| s.on_line(line, height))
+        {
+            if sprites.drawn.len() < SPRITES_PER_LINE {
+                sprites.drawn.push(sprite.number);
+            } else {
+                sprites.dropped.push(sprite.number);
+            }
+        }
+        sprites
+    }
+}
+
+// Every sprite in OAM drawn as one image, in OAM order, each framed green
+// if it's drawn on a given line of the screen and red if it was dropped
+// from it
+pub struct SpriteSheet;
+
+impl SpriteSheet {
+    pub fn width(&self) -> u32 {
+        SHEET_COLUMNS * CELL_WIDTH
+    }
+
+    pub fn height(&self) -> u32 {
+        SHEET_ROWS * CELL_HEIGHT
+    }
+
+    // 3 bytes (RGB) per pixel, row by row
+    pub fn render(&self, mmu: &MMU, line: u8) -> Vec<u8> {
+        let width = self.width() as usize;
+        let mut pixels = vec![0; width * self.height() as usize * 3];
+        let line = LineSprites::new(mmu, line);
+        for sprite in Sprite::all(mmu) {
+            let (left, top) = self.position(sprite.number);
+            let border = if line.drawn.contains(&sprite.number) {
+                DRAWN_COLOR
+            } else if line.dropped.contains(&sprite.number) {
+                DROPPED_COLOR
+            } else {
+                EMPTY_COLOR
+            };
+            for y in 0..CELL_HEIGHT {
+                for x in 0..CELL_WIDTH {
+                    let inside = (BORDER..CELL_WIDTH - BORDER).contains(&x)
+                        && (BORDER..CELL_HEIGHT - BORDER).contains(&y);
+                    let color = if inside { EMPTY_COLOR } else { border };
+                    let (x, y) = ((left + x) as usize, (top + y) as usize);
+                    set_pixel(&mut pixels, width, x, y, color);
+                }
+            }
+
+            // CGB color palettes aren't emulated, so CGB games are shown raw
+            let mut colors = if mmu.cgb_mode {
+                TilePalette::Raw.colors(mmu)
+            } else {
+                sprite.dmg_palette().colors(mmu)
+            };
+            colors[0] = TRANSPARENT_COLOR;
+            let (left, top) = (left + BORDER, top + BORDER);
+            for (y, row) in sprite.color_ids(mmu).iter().enumerate() {
+                for (x, &id) in row.iter().enumerate() {
+                    let (x, y) = (left as usize + x, top as usize + y);
+                    set_pixel(&mut pixels, width, x, y, colors[id as usize]);
+                }
+            }
+        }
+        pixels
+    }
+
+    // The top left pixel of a sprite's cell, border included
+    pub fn position(&self, number: u8) -> (u32, u32) {
+        let number = number as u32;
+        (
+            number % SHEET_COLUMNS * CELL_WIDTH,
+            number / SHEET_COLUMNS * CELL_HEIGHT,
+        )
+    }
+
+    // The OAM number of the sprite whose cell holds a pixel of the sheet
+    pub fn sprite_at(&self, x: u32, y: u32) -> Option<u8> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        Some((y / CELL_HEIGHT * SHEET_COLUMNS + x / CELL_WIDTH) as u8)
+    }
+}
@@ -0,0 +1,306 @@
+use crate::debugger::ErrorPolicy;
+use crate::headless::ExitCondition;
+use crate::logging::LogFilter;
+use rustboy::model::Model;
+use rustboy::trace::PcRange;
+
+// What to plug into the link port
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Link {
+    Disconnected,
+    Stdout,          // Print what the game sends, for test ROMs that report over serial
+    Printer,         // A Game Boy Printer that saves what it prints as PNGs
+    Listen(String),  // Wait for another rustboy to connect on this host:port
+    Connect(String), // Connect to another rustboy at host:port
+}
+
+impl std::str::FromStr for Link {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Link::Disconnected),
+            "stdout" => Ok(Link::Stdout),
+            "printer" => Ok(Link::Printer),
+            _ => Err(format!("Unknown link device: {}", s)),
+        }
+    }
+}
+
+// Command-line options for the emulator. Some only matter to the window
+// frontend, so go unused in builds without it.
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+pub struct Options {
+    pub rom_path: String,
+    pub record_audio: Option<String>, // WAV file to record the audio output to
+    pub record_channels: bool,        // Also record each APU channel to its own file
+    pub track: Option<u8>,            // 1-based track to start on when playing a GBS file
+    pub boot_rom: Option<String>,     // Boot ROM to run before the cartridge
+    pub model: Option<Model>,         // Hardware model to emulate
+    pub rewind_seconds: u32,          // How far back rewinding can go, 0 to disable
+    pub rewind_interval: u32,         // Frames between rewind snapshots
+    pub headless: bool,               // Run without a window or audio device
+    pub frames: Option<u64>,          // Stop a headless run after this many frames
+    pub cycles: Option<u64>,          // Stop a headless run after this many CPU cycles
+    pub exit_on: Option<ExitCondition>, // Stop a headless run when this happens
+    pub screenshot_at: Option<(u64, String)>, // Frame to save a screenshot after, and where
+    pub screenshot_scale: u32,        // Also save screenshots scaled up this much, if above 1
+    pub link: Link,                   // Link port device
+    pub dual: bool,                   // Run two linked Game Boys in lockstep
+    pub dual_rom: Option<String>,     // Game for the second Game Boy, if not the same one
+    pub dual_windows: bool,           // Show the two Game Boys in separate windows
+    pub debug: bool,                  // Start in the debugger
+    pub gdb: Option<u16>,             // Port to wait for GDB on
+    pub trace: Option<String>,        // File to log every instruction to
+    pub trace_range: Option<PcRange>, // Only log instructions in this address range
+    pub trace_bank: Option<u16>,      // Only log instructions run from this bank
+    pub trace_last: usize,            // Instructions to keep for dumping on a crash
+    pub on_error: ErrorPolicy,        // What to do when the CPU can't execute an instruction
+    pub log: Option<LogFilter>,       // Which log messages to show, instead of RUSTBOY_LOG
+    pub tile_viewer: bool,            // Open the VRAM tile viewer at the start
+    pub map_viewer: bool,             // Open the tile map viewer at the start
+    pub sprite_viewer: bool,          // Open the OAM sprite viewer at the start
+}
+
+const USAGE: &str = "Usage: rustboy <rom|file.gbs> [options]
+       rustboy disasm <rom> [--bank <n>] [--from <addr>] [--to <addr> | --count <n>]
+
+Options:
+  --record-audio <file.wav>  Record the audio output to a WAV file. There's no APU
+                             yet, so recordings are silent
+  --record-channels          Also write one WAV file per APU channel
+  --track <n>                Track to start on when playing a GBS file
+  --bootrom <file>           Run a boot ROM before the cartridge
+  --model <model>            Hardware model: dmg0, dmg, mgb, sgb, sgb2, cgb or agb
+  --rewind-seconds <n>       Seconds of rewind history to keep, 0 to disable (default 10)
+  --rewind-interval <n>      Frames between rewind snapshots (default 2)
+  --headless                 Run without a window or audio device
+  --frames <n>               Stop a headless run after n frames
+  --cycles <n>               Stop a headless run after n CPU cycles
+  --exit-on <condition>      Stop a headless run at pc=<hex address> or on ld-b-b
+  --screenshot-at-frame <n> <file.png>
+                             Save a screenshot once n frames have run
+  --screenshot-scale <n>     Also save each screenshot scaled up n times
+  --link <device>            Link port device: none (default), stdout or printer
+  --link-listen [host:]<port>
+                             Link to another rustboy that connects on this port,
+                             only from this machine unless a host is given
+  --link-connect <host:port> Link to another rustboy listening at this address
+  --dual                     Run two Game Boys linked together, side by side
+  --dual-rom <file>          Like --dual, with a different game on the second
+  --dual-windows             Show the two Game Boys in separate windows
+  --debug                    Start paused in the debugger (F11 breaks in while running)
+  --gdb <port>               Wait for GDB to attach on this port and let it debug
+  --trace <file>             Log the registers and PCMEM before every instruction, in
+                             the Gameboy Doctor format (the only one supported)
+  --trace-range <start-end>  Only log instructions at these addresses, in hex
+  --trace-bank <n>           Only log instructions run from this bank
+  --trace-last <n>           Print the last n instructions on a crash or unknown opcode
+  --on-error <policy>        When the CPU hits an illegal or unimplemented opcode:
+                             halt, debug or continue (default)
+  --log <filter>             Log levels: a default and per target, like info,mmu=trace.
+                             Targets: cpu, mmu, ppu, apu, mbc and serial. Levels: off,
+                             error, warn, info (default), debug and trace; debug and
+                             trace are compiled out of release builds. Also read from
+                             the RUSTBOY_LOG environment variable
+  --tile-viewer              Open the VRAM tile viewer (V toggles it while running)
+  --map-viewer               Open the tile map viewer (M toggles it while running)
+  --sprite-viewer            Open the OAM sprite viewer (O toggles it while running)";
+
+impl Options {
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Self {
+        let mut rom_path = None;
+        let mut record_audio = None;
+        let mut record_channels = false;
+        let mut track = None;
+        let mut boot_rom = None;
+        let mut model = None;
+        let mut rewind_seconds = 10;
+        let mut rewind_interval = 2;
+        let mut headless = false;
+        let mut frames = None;
+        let mut cycles = None;
+        let mut exit_on = None;
+        let mut screenshot_at = None;
+        let mut screenshot_scale = 1;
+        let mut link = Link::Disconnected;
+        let mut dual = false;
+        let mut dual_rom = None;
+        let mut dual_windows = false;
+        let mut debug = false;
+        let mut gdb = None;
+        let mut trace = None;
+        let mut trace_range = None;
+        let mut trace_bank = None;
+        let mut trace_last = 0;
+        let mut on_error = ErrorPolicy::Continue;
+        let mut log = None;
+        let mut tile_viewer = false;
+        let mut map_viewer = false;
+        let mut sprite_viewer = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record-audio" => record_audio = Some(Self::value(&mut args, &arg)),
+                "--record-channels" => record_channels = true,
+                "--track" => track = Some(Self::number(&mut args, &arg)),
+                "--bootrom" => boot_rom = Some(Self::value(&mut args, &arg)),
+                "--model" => {
+                    let value = Self::value(&mut args, &arg);
+                    model = Some(
+                        value
+                            .parse()
+                            .unwrap_or_else(|e: String| Self::exit_with_usage(Some(&e))),
+                    );
+                }
+                "--rewind-seconds" => rewind_seconds = Self::number(&mut args, &arg),
+                "--rewind-interval" => {
+                    rewind_interval = Self::number(&mut args, &arg);
+                    if rewind_interval == 0 {
+                        Self::exit_with_usage(Some("--rewind-interval must be at least 1"));
+                    }
+                }
+                "--headless" => headless = true,
+                "--frames" => frames = Some(Self::number(&mut args, &arg)),
+                "--cycles" => cycles = Some(Self::number(&mut args, &arg)),
+                "--exit-on" => {
+                    let value = Self::value(&mut args, &arg);
+                    exit_on = Some(
+                        value
+                            .parse()
+                            .unwrap_or_else(|e: String| Self::exit_with_usage(Some(&e))),
+                    );
+                }
+                "--screenshot-at-frame" => {
+                    let frame = Self::number(&mut args, &arg);
+                    screenshot_at = Some((frame, Self::value(&mut args, &arg)));
+                }
+                "--screenshot-scale" => screenshot_scale = Self::number(&mut args, &arg),
+                "--link" => {
+                    let value = Self::value(&mut args, &arg);
+                    link = value
+                        .parse()
+                        .unwrap_or_else(|e: String| Self::exit_with_usage(Some(&e)));
+                }
+                "--link-listen" => {
+                    let address = Self::value(&mut args, &arg);
+                    link = Link::Listen(if address.contains(':') {
+                        address
+                    } else {
+                        format!("127.0.0.1:{}", address)
+                    });
+                }
+                "--link-connect" => link = Link::Connect(Self::value(&mut args, &arg)),
+                "--dual" => dual = true,
+                "--dual-rom" => {
+                    dual = true;
+                    dual_rom = Some(Self::value(&mut args, &arg));
+                }
+                "--dual-windows" => dual_windows = true,
+                "--debug" => debug = true,
+                "--gdb" => gdb = Some(Self::number(&mut args, &arg)),
+                "--trace" => trace = Some(Self::value(&mut args, &arg)),
+                "--trace-range" => {
+                    let value = Self::value(&mut args, &arg);
+                    trace_range = Some(
+                        value
+                            .parse()
+                            .unwrap_or_else(|e: String| Self::exit_with_usage(Some(&e))),
+                    );
+                }
+                "--trace-bank" => trace_bank = Some(Self::number(&mut args, &arg)),
+                "--trace-last" => trace_last = Self::number(&mut args, &arg),
+                "--on-error" => {
+                    let value = Self::value(&mut args, &arg);
+                    on_error = value
+                        .parse()
+                        .unwrap_or_else(|e: String| Self::exit_with_usage(Some(&e)));
+                }
+                "--log" => {
+                    let value = Self::value(&mut args, &arg);
+                    log = Some(
+                        value
+                            .parse()
+                            .unwrap_or_else(|e: String| Self::exit_with_usage(Some(&e))),
+                    );
+                }
+                "--tile-viewer" => tile_viewer = true,
+                "--map-viewer" => map_viewer = true,
+                "--sprite-viewer" => sprite_viewer = true,
+                "-h" | "--help" => Self::exit_with_usage(None),
+                _ if arg.starts_with("--") => {
+                    Self::exit_with_usage(Some(&format!("Unknown option: {}", arg)))
+                }
+                _ => rom_path = Some(arg),
+            }
+        }
+
+        let rom_path =
+            rom_path.unwrap_or_else(|| Self::exit_with_usage(Some("Please provide a ROM file.")));
+
+        Self {
+            rom_path,
+            record_audio,
+            record_channels,
+            track,
+            boot_rom,
+            model,
+            rewind_seconds,
+            rewind_interval,
+            headless,
+            frames,
+            cycles,
+            exit_on,
+            screenshot_at,
+            screenshot_scale,
+            link,
+            dual,
+            dual_rom,
+            dual_windows,
+            debug,
+            gdb,
+            trace,
+            trace_range,
+            trace_bank,
+            trace_last,
+            on_error,
+            log,
+            tile_viewer,
+            map_viewer,
+            sprite_viewer,
+        }
+    }
+
+    // Fetch the value following an option, exiting if it is missing
+    fn value(args: &mut impl Iterator<Item = String>, option: &str) -> String {
+        args.next().unwrap_or_else(|| {
+            Self::exit_with_usage(Some(&format!("Missing value for {}", option)))
+        })
+    }
+
+    // Fetch and parse a numeric value, accepting decimal or 0x-prefixed hex
+    fn number<T: TryFrom<u64>>(args: &mut impl Iterator<Item = String>, option: &str) -> T {
+        let value = Self::value(args, option);
+        let parsed = match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse::<u64>().ok(),
+        };
+        parsed.and_then(|n| T::try_from(n).ok()).unwrap_or_else(|| {
+            Self::exit_with_usage(Some(&format!("Invalid value for {}: {}", option, value)))
+        })
+    }
+
+    fn exit_with_usage(error: Option<&str>) -> ! {
+        match error {
+            Some(error) => {
+                eprintln!("{}\n\n{}", error, USAGE);
+                std::process::exit(1);
+            }
+            None => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+        }
+    }
+}
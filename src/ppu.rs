@@ -0,0 +1,102 @@
+use crate::mmu::MMU;
+use crate::model::Model;
+use crate::vram::{TileMap, MAP_SIZE};
+
+pub const SCREEN_WIDTH: u32 = 160;
+pub const SCREEN_HEIGHT: u32 = 144;
+
+// Shades for color IDs 0-3 on each kind of screen
+const DMG_PALETTE: [(u8, u8, u8); 4] = [(155, 188, 15), (139, 172, 15), (48, 98, 48), (15, 56, 15)];
+const GREY_PALETTE: [(u8, u8, u8); 4] = [(255, 255, 255), (192, 192, 192), (96, 96, 96), (0, 0, 0)];
+// What the CGB boot ROM assigns monochrome games it has no palette for
+const CGB_COMPAT_PALETTE: [(u8, u8, u8); 4] =
+    [(255, 255, 255), (123, 255, 49), (0, 99, 197), (0, 0, 0)];
+
+// Draws the screen from VRAM. Only the background tile map is drawn so far,
+// once per frame rather than line by line.
+pub struct Ppu {
+    framebuffer: Vec<u8>, // 3 bytes per pixel (RGB), row by row
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            framebuffer: vec![0; (SCREEN_WIDTH * SCREEN_HEIGHT * 3) as usize], // Black
+        }
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    // The framebuffer blown up `scale` times in each direction, with every
+    // pixel repeated so the edges stay sharp
+    pub fn scaled_framebuffer(&self, scale: u32) -> Vec<u8> {
+        let scale = scale.max(1) as usize;
+        let row_bytes = SCREEN_WIDTH as usize * 3;
+        let mut scaled = Vec::with_capacity(self.framebuffer.len() * scale * scale);
+        for row in self.framebuffer.chunks(row_bytes) {
+            let mut scaled_row = Vec::with_capacity(row_bytes * scale);
+            for pixel in row.chunks(3) {
+                for _ in 0..scale {
+                    scaled_row.extend_from_slice(pixel);
+                }
+            }
+            for _ in 0..scale {
+                scaled.extend_from_slice(&scaled_row);
+            }
+        }
+        scaled
+    }
+
+    // Draw the part of the background map the screen shows: 160x144 pixels
+    // from SCX/SCY on, wrapping around the map's edges
+    pub fn render_tile_map(&mut self, mmu: &MMU) {
+        let palette = Self::palette_for(mmu.model, mmu.cgb_mode);
+        let ids = TileMap::background(mmu).color_ids(mmu);
+        let (scx, scy) = (mmu.memory[0xFF43] as u32, mmu.memory[0xFF42] as u32);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let map_x = (scx + x) % MAP_SIZE;
+                let map_y = (scy + y) % MAP_SIZE;
+                let id = ids[(map_y * MAP_SIZE + map_x) as usize];
+                self.set_pixel(x, y, palette[id as usize]);
+            }
+        }
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, (r, g, b): (u8, u8, u8)) {
+        let idx = ((y * SCREEN_WIDTH + x) * 3) as usize;
+        self.framebuffer[idx] = r;
+        self.framebuffer[idx + 1] = g;
+        self.framebuffer[idx + 2] = b;
+    }
+
+    // The original Game Boy's green-tinted LCD, the Pocket and Super Game Boy's
+    // grey, and the palette a CGB colorizes monochrome games with
+    pub(crate) fn palette_for(model: Model, cgb_mode: bool) -> [(u8, u8, u8); 4] {
+        match model {
+            Model::Dmg0 | Model::Dmg => DMG_PALETTE,
+            Model::Cgb | Model::Agb if !cgb_mode => CGB_COMPAT_PALETTE,
+            _ => GREY_PALETTE, // CGB color palettes aren't emulated yet
+        }
+    }
+}
+
+// The color IDs (0-3) of a tile's 8x8 pixels, row by row. Each row is two
+// bytes: the low bit of every pixel's ID, then the high bit, leftmost first.
+pub fn decode_tile(tile_data: &[u8; 16]) -> [[u8; 8]; 8] {
+    std::array::from_fn(|ty| {
+        let (low, high) = (tile_data[ty * 2], tile_data[ty * 2 + 1]);
+        std::array::from_fn(|tx| {
+            let bit = 7 - tx;
+            ((low >> bit) & 1) | (((high >> bit) & 1) << 1)
+        })
+    })
+}
@@ -0,0 +1,307 @@
+use crate::audio::CYCLES_PER_FRAME;
+use crate::png;
+use crate::serial::LinkDevice;
+
+// Packets start with these two bytes
+const MAGIC: [u8; 2] = [0x88, 0x33];
+
+// What the printer answers to the first of the two bytes after a packet
+const ALIVE: u8 = 0x81;
+
+// Commands
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+// Status bits
+const CHECKSUM_ERROR: u8 = 0x01;
+const BUSY: u8 = 0x02;
+const IMAGE_FULL: u8 = 0x04;
+const UNPROCESSED_DATA: u8 = 0x08;
+const PACKET_ERROR: u8 = 0x10;
+
+// The printed image is 160 pixels (20 tiles) wide, and each data packet
+// holds one 16 pixel band of it
+const WIDTH: usize = 160;
+const BAND_BYTES: usize = 0x280;
+
+// The printer's RAM holds 9 bands, a little more than one screen
+const MAX_BUFFER: usize = 9 * BAND_BYTES;
+
+// Each unit of margin feeds this many blank pixel rows of paper
+const MARGIN_ROWS: usize = 8;
+
+// How long the print head takes per pixel row
+const CYCLES_PER_ROW: u32 = CYCLES_PER_FRAME / 8;
+
+// Paper shades for colour numbers 0-3
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+// Where we are in the packet being received
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+// A Game Boy Printer. Games send it packets of tile data followed by a print
+// command; every sheet of paper fed out is saved as `<stem>_<n>.png`. Prints
+// without a margin after them carry on down the same sheet, which is how
+// games print pictures taller than the printer's memory.
+pub struct Printer {
+    path: String,
+    sheets: usize,
+
+    stage: Stage,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    data: Vec<u8>,
+    checksum: u16,
+    status: u8,
+
+    buffer: Vec<u8>, // Decompressed tile data waiting to be printed
+    paper: Vec<u8>,  // Colour numbers of the sheet being printed, one per pixel
+    busy_cycles: u32,
+}
+
+impl Printer {
+    // A printer saving its sheets next to `path`, which should end in .png
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.strip_suffix(".png").unwrap_or(path).to_string(),
+            sheets: 0,
+            stage: Stage::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            status: 0,
+            buffer: Vec::new(),
+            paper: Vec::new(),
+            busy_cycles: 0,
+        }
+    }
+
+    // Take the next byte of a packet and return what the printer sends back
+    fn receive(&mut self, byte: u8) -> u8 {
+        match self.stage {
+            Stage::Magic(i) => {
+                if byte == MAGIC[i] {
+                    self.stage = if i == 0 {
+                        Stage::Magic(1)
+                    } else {
+                        Stage::Command
+                    };
+                } else {
+                    // Resynchronise, in case this byte starts a new packet
+                    self.stage = Stage::Magic(usize::from(byte == MAGIC[0]));
+                }
+                self.checksum = 0;
+            }
+            Stage::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                self.stage = Stage::Compression;
+            }
+            Stage::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.stage = Stage::LengthLow;
+            }
+            Stage::LengthLow => {
+                self.length = byte as usize;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.stage = Stage::LengthHigh;
+            }
+            Stage::LengthHigh => {
+                self.length |= (byte as usize) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                self.stage = if self.length > 0 {
+                    Stage::Data
+                } else {
+                    Stage::ChecksumLow
+                };
+            }
+            Stage::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.length {
+                    self.stage = Stage::ChecksumLow;
+                }
+            }
+            Stage::ChecksumLow => {
+                self.checksum ^= byte as u16;
+                self.stage = Stage::ChecksumHigh;
+            }
+            Stage::ChecksumHigh => {
+                // Whatever is left is zero only if the checksum matched
+                self.checksum ^= (byte as u16) << 8;
+                if self.checksum == 0 {
+                    self.status &= !(CHECKSUM_ERROR | PACKET_ERROR);
+                    self.execute();
+                } else {
+                    self.status |= CHECKSUM_ERROR;
+                }
+                self.stage = Stage::Alive;
+            }
+            Stage::Alive => {
+                self.stage = Stage::Status;
+                return ALIVE;
+            }
+            Stage::Status => {
+                self.stage = Stage::Magic(0);
+                return self.status();
+            }
+        }
+        0x00
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = self.status;
+        if self.busy_cycles > 0 {
+            status |= BUSY;
+        }
+        if self.buffer.len() >= MAX_BUFFER {
+            status |= IMAGE_FULL;
+        }
+        status
+    }
+
+    // Carry out a packet that arrived intact
+    fn execute(&mut self) {
+        match self.command {
+            INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            }
+            DATA => {
+                let data = if self.compressed {
+                    decompress(&self.data)
+                } else {
+                    std::mem::take(&mut self.data)
+                };
+                let space = MAX_BUFFER - self.buffer.len();
+                self.buffer
+                    .extend_from_slice(&data[..data.len().min(space)]);
+                if !self.buffer.is_empty() {
+                    self.status |= UNPROCESSED_DATA;
+                }
+            }
+            PRINT if self.data.len() == 4 => {
+                let margins = self.data[1];
+                let palette = self.data[2];
+                self.print(margins >> 4, margins & 0x0F, palette);
+            }
+            STATUS => {}
+            _ => self.status |= PACKET_ERROR,
+        }
+    }
+
+    // Feed the buffered image out onto the paper between the given margins.
+    // The fourth byte of a print command, the exposure, is ignored.
+    fn print(&mut self, margin_before: u8, margin_after: u8, palette: u8) {
+        // Most games leave the palette at 0, which prints like the usual 0xE4
+        let palette = if palette == 0 { 0xE4 } else { palette };
+
+        self.feed(margin_before as usize * MARGIN_ROWS);
+        let rows = self.buffer.len() / BAND_BYTES * 16;
+        for y in 0..rows {
+            for x in 0..WIDTH {
+                // 20 tiles to a row of tiles, 16 bytes to a tile, 2 to a line
+                let tile = y / 8 * (WIDTH / 8) + x / 8;
+                let offset = tile * 16 + y % 8 * 2;
+                let bit = 7 - x % 8;
+                let low = (self.buffer[offset] >> bit) & 1;
+                let high = (self.buffer[offset + 1] >> bit) & 1;
+                let colour = (high << 1) | low;
+                self.paper.push((palette >> (colour * 2)) & 0x03);
+            }
+        }
+        self.feed(margin_after as usize * MARGIN_ROWS);
+
+        self.buffer.clear();
+        self.status &= !UNPROCESSED_DATA;
+        self.busy_cycles = (rows as u32).max(1) * CYCLES_PER_ROW;
+
+        // A margin after the image means the sheet is done
+        if margin_after > 0 {
+            self.save_sheet();
+        }
+    }
+
+    // Feed blank paper
+    fn feed(&mut self, rows: usize) {
+        self.paper.resize(self.paper.len() + rows * WIDTH, 0);
+    }
+
+    // Write out the sheet printed so far, if there is one
+    fn save_sheet(&mut self) {
+        if self.paper.is_empty() {
+            return;
+        }
+        self.sheets += 1;
+        let path = format!("{}_{}.png", self.path, self.sheets);
+        let height = self.paper.len() / WIDTH;
+        let pixels: Vec<u8> = self
+            .paper
+            .iter()
+            .flat_map(|&colour| [SHADES[colour as usize]; 3])
+            .collect();
+        match png::write_rgb(&path, WIDTH as u32, height as u32, &pixels) {
+            Ok(()) => log::info!(target: "serial", "Printed to {}", path),
+            Err(e) => log::error!(target: "serial", "Failed to save print to {}: {}", path, e),
+        }
+        self.paper.clear();
+    }
+}
+
+impl LinkDevice for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.receive(byte)
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.busy_cycles = self.busy_cycles.saturating_sub(cycles);
+    }
+}
+
+impl Drop for Printer {
+    // Don't lose a sheet that never got its final margin
+    fn drop(&mut self) {
+        self.save_sheet();
+    }
+}
+
+// Undo the printer's run-length encoding. A control byte with the top bit
+// set is followed by one byte to repeat (control & 0x7F) + 2 times;
+// otherwise it's followed by control + 1 bytes to copy as they are.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(BAND_BYTES);
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            let Some(&byte) = data.get(i) else { break };
+            output.extend(std::iter::repeat_n(byte, (control & 0x7F) as usize + 2));
+            i += 1;
+        } else {
+            let end = (i + control as usize + 1).min(data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    output
+}
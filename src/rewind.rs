@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+
+use crate::gameboy::GameBoy;
+
+// Keeps a rolling history of save states so the game can be played backwards.
+//
+// Only the newest snapshot is kept whole. Every older one is stored as the XOR
+// of itself and the snapshot taken after it, run-length encoded. Consecutive
+// states differ in a handful of bytes, so the XOR is mostly zeros and packs
+// down to almost nothing, and stepping backwards only ever needs the newer
+// neighbour we already have.
+pub struct Rewind {
+    interval: u32, // Frames between snapshots
+    capacity: usize,
+    frames_until_snapshot: u32,
+    frames_until_step_back: u32, // Frames left to show the last restored snapshot
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>, // Oldest first
+}
+
+impl Rewind {
+    pub fn new(seconds: u32, interval: u32) -> Self {
+        let interval = interval.max(1);
+        Self {
+            interval,
+            capacity: (seconds as usize).saturating_mul(60) / interval as usize,
+            frames_until_snapshot: 0,
+            frames_until_step_back: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    // Call once per emulated frame; takes a snapshot every `interval` frames
+    pub fn record(&mut self, gameboy: &GameBoy) {
+        if !self.enabled() {
+            return;
+        }
+        // Playing again, so the next rewind starts straight away
+        self.frames_until_step_back = 0;
+        if self.frames_until_snapshot > 0 {
+            self.frames_until_snapshot -= 1;
+            return;
+        }
+        self.frames_until_snapshot = self.interval - 1;
+
+        let snapshot = gameboy.save_state();
+        if let Some(previous) = self.latest.take() {
+            if previous.len() == snapshot.len() {
+                self.deltas.push_back(compress(&xor(&previous, &snapshot)));
+            } else {
+                // Deltas only work between equally sized states
+                self.deltas.clear();
+            }
+        }
+        self.latest = Some(snapshot);
+
+        while self.deltas.len() >= self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    // Call once per frame while rewinding. Restores the newest snapshot and
+    // drops it from the history, then holds it for the `interval` frames it
+    // stood for so rewinding runs at the speed the game played. Returns false
+    // once there is nothing left to go back to.
+    pub fn step_back(&mut self, gameboy: &mut GameBoy) -> bool {
+        if self.frames_until_step_back > 0 {
+            self.frames_until_step_back -= 1;
+            return true;
+        }
+        let Some(snapshot) = self.latest.take() else {
+            return false;
+        };
+
+        if let Err(e) = gameboy.load_state(&snapshot) {
+            log::error!("Rewind failed, clearing history: {}", e);
+            self.deltas.clear();
+            return false;
+        }
+
+        // Rebuild the snapshot before this one so the next step can use it
+        self.latest = self
+            .deltas
+            .pop_back()
+            .map(|delta| xor(&snapshot, &decompress(&delta, snapshot.len())));
+
+        self.frames_until_step_back = self.interval - 1;
+        // Give the player a moment at the restored point before recording again
+        self.frames_until_snapshot = self.interval;
+        true
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+// Encode as alternating (zero run length, literal length, literal bytes)
+// groups, with the lengths written as LEB128 varints
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let zeros = data[pos..].iter().take_while(|&&b| b == 0).count();
+        pos += zeros;
+        let literals = data[pos..].iter().take_while(|&&b| b != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend_from_slice(&data[pos..pos + literals]);
+        pos += literals;
+    }
+    out
+}
+
+fn decompress(data: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len);
+    let mut pos = 0;
+    while pos < data.len() {
+        let zeros = read_varint(data, &mut pos);
+        let literals = read_varint(data, &mut pos);
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(&data[pos..pos + literals]);
+        pos += literals;
+    }
+    out.resize(len, 0);
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
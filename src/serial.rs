@@ -0,0 +1,111 @@
+use std::io::Write;
+
+use crate::savestate::{StateReader, StateWriter};
+
+// Cycles per bit on the internal clock: 8192 Hz normally, 262144 Hz with the
+// CGB's fast clock (SC bit 1)
+const CYCLES_PER_BIT: u32 = 512;
+const FAST_CYCLES_PER_BIT: u32 = 16;
+
+// Whatever is plugged into the link port. Bytes are exchanged whole when a
+// transfer finishes rather than bit by bit.
+pub trait LinkDevice {
+    // We clocked a transfer: take the byte we sent and return the one shifted back in
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    // We're waiting on the external clock with `byte` in SB. If the other
+    // end has clocked a transfer, take our byte and return the one it sent.
+    fn external_transfer(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+
+    // Called while no transfer is under way, with `byte` in SB. If the other
+    // end clocks a transfer now, it shifts in whatever is in SB.
+    fn idle(&mut self, _byte: u8) {}
+
+    // Called as emulated time passes, for devices that need to keep in step
+    fn tick(&mut self, _cycles: u32) {}
+}
+
+// Nothing plugged in: the line floats high, so every transfer reads 0xFF and
+// an externally clocked one never finishes
+pub struct Disconnected;
+
+impl LinkDevice for Disconnected {
+    fn transfer(&mut self, _byte: u8) -> u8 {
+        0xFF
+    }
+}
+
+// Prints every byte sent to stdout, which is how Blargg's test ROMs report
+// their results. Otherwise behaves as if nothing is plugged in.
+pub struct StdoutLogger;
+
+impl LinkDevice for StdoutLogger {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(&[byte]);
+        let _ = stdout.flush();
+        0xFF
+    }
+}
+
+// The serial port's transfer state. SB (0xFF01) and SC (0xFF02) themselves
+// live in memory like the other IO registers.
+pub struct Serial {
+    pub device: Box<dyn LinkDevice>,
+    cycles_left: u32, // Until the internally clocked transfer in progress finishes
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            device: Box::new(Disconnected),
+            cycles_left: 0,
+        }
+    }
+
+    // SC was written with the start bit set. On the internal clock this
+    // starts the countdown; on the external clock we wait on the other end.
+    pub fn start(&mut self, sc: u8, cgb_mode: bool) {
+        let cycles_per_bit = if cgb_mode && sc & 0x02 != 0 {
+            FAST_CYCLES_PER_BIT
+        } else {
+            CYCLES_PER_BIT
+        };
+        self.cycles_left = 8 * cycles_per_bit;
+    }
+
+    // Advance a transfer by `cycles` and return the received byte if it finished
+    pub fn tick(&mut self, sb: u8, sc: u8, cycles: u32) -> Option<u8> {
+        self.device.tick(cycles);
+        if sc & 0x80 == 0 {
+            self.device.idle(sb);
+            return None;
+        }
+        if sc & 0x01 == 0 {
+            return self.device.external_transfer(sb);
+        }
+
+        self.cycles_left = self.cycles_left.saturating_sub(cycles);
+        if self.cycles_left > 0 {
+            return None;
+        }
+        Some(self.device.transfer(sb))
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.cycles_left);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.cycles_left = r.read_u32()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "sdl")]
+use crate::input::Hotkey;
+
+// One frame is 70224 CPU cycles at 4194304 Hz, so the Game Boy runs at ~59.73 FPS
+pub const FRAME_DURATION: Duration = Duration::from_nanos(70224 * 1_000_000_000 / 4194304);
+
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+
+// If we fall this far behind (e.g. the window was dragged), stop trying to
+// catch up and pace from now instead
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+// Paces emulated frames against the wall clock and handles the speed
+// controls: fixed multipliers, turbo, pause and frame advance
+pub struct SpeedControl {
+    speed_index: usize,
+    turbo: bool, // Uncapped while held
+    paused: bool,
+    advance: bool, // Run a single frame while paused
+    next_frame: Instant,
+}
+
+impl SpeedControl {
+    pub fn new() -> Self {
+        Self {
+            speed_index: NORMAL_SPEED,
+            turbo: false,
+            paused: false,
+            advance: false,
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed_index]
+    }
+
+    pub fn faster(&mut self) {
+        self.speed_index = (self.speed_index + 1).min(SPEEDS.len() - 1);
+        println!("Speed: {}x", self.speed());
+    }
+
+    pub fn slower(&mut self) {
+        self.speed_index = self.speed_index.saturating_sub(1);
+        println!("Speed: {}x", self.speed());
+    }
+
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        println!("{}", if self.paused { "Paused" } else { "Resumed" });
+    }
+
+    // Pause if needed and queue up exactly one frame
+    pub fn frame_advance(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    #[cfg(feature = "sdl")]
+    pub fn handle_hotkey(&mut self, hotkey: Hotkey) {
+        match hotkey {
+            Hotkey::TogglePause => self.toggle_pause(),
+            Hotkey::FrameAdvance => self.frame_advance(),
+            Hotkey::SpeedUp => self.faster(),
+            Hotkey::SlowDown => self.slower(),
+            _ => {}
+        }
+    }
+
+    // Whether the emulator should run a frame this time around the loop
+    pub fn should_run_frame(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        std::mem::take(&mut self.advance)
+    }
+
+    // Anything other than real time; audio is muted rather than played at the wrong pitch
+    pub fn is_normal_speed(&self) -> bool {
+        !self.turbo && !self.paused && self.speed_index == NORMAL_SPEED
+    }
+
+    // Sleep until the next frame is due. Deadlines advance by a fixed step so
+    // rounding in sleep() doesn't accumulate into drift.
+    pub fn wait_for_next_frame(&mut self) {
+        let now = Instant::now();
+        self.next_frame = next_frame_due(self.next_frame, now, self.speed(), self.turbo);
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+        }
+    }
+}
+
+// When the frame after one that was due at `due` is due, if it's now `now`:
+// a frame's time later at `speed`, or straight away in turbo or once we've
+// fallen too far behind
+fn next_frame_due(due: Instant, now: Instant, speed: f64, turbo: bool) -> Instant {
+    if turbo {
+        return now;
+    }
+    let next = due + FRAME_DURATION.div_f64(speed);
+    if next <= now && now - next > MAX_LAG {
+        now
+    } else {
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_paced_at_the_speed_multiplier() {
+        let start = Instant::now();
+        let due = |speed| next_frame_due(start, start, speed, false) - start;
+        assert_eq!(due(1.0), FRAME_DURATION);
+        assert_eq!(due(2.0), FRAME_DURATION / 2);
+        assert_eq!(due(0.25), FRAME_DURATION * 4);
+        assert!((FRAME_DURATION.as_secs_f64() * 4194304.0 / 70224.0 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn deadlines_advance_by_a_fixed_step() {
+        // Running a little late doesn't push later frames back
+        let start = Instant::now();
+        let late = start + FRAME_DURATION + Duration::from_millis(5);
+        let due = next_frame_due(start, late, 1.0, false);
+        assert_eq!(due, start + FRAME_DURATION);
+        assert_eq!(
+            next_frame_due(due, late, 1.0, false),
+            start + FRAME_DURATION * 2
+        );
+    }
+
+    #[test]
+    fn falling_far_behind_paces_from_now() {
+        let start = Instant::now();
+        let now = start + FRAME_DURATION + MAX_LAG + Duration::from_millis(1);
+        assert_eq!(next_frame_due(start, now, 1.0, false), now);
+    }
+
+    #[test]
+    fn turbo_runs_frames_straight_away() {
+        let start = Instant::now();
+        let now = start + Duration::from_millis(1);
+        assert_eq!(next_frame_due(start, now, 1.0, true), now);
+    }
+
+    #[test]
+    fn speed_controls() {
+        let mut speed = SpeedControl::new();
+        assert_eq!(speed.speed(), 1.0);
+        assert!(speed.is_normal_speed());
+
+        for _ in 0..10 {
+            speed.faster();
+        }
+        assert_eq!(speed.speed(), 8.0);
+        for _ in 0..10 {
+            speed.slower();
+        }
+        assert_eq!(speed.speed(), 0.25);
+        assert!(!speed.is_normal_speed());
+
+        let mut speed = SpeedControl::new();
+        speed.set_turbo(true);
+        assert!(!speed.is_normal_speed());
+        speed.set_turbo(false);
+        assert!(speed.is_normal_speed());
+    }
+
+    #[test]
+    fn frame_advance_runs_one_frame_while_paused() {
+        let mut speed = SpeedControl::new();
+        assert!(speed.should_run_frame());
+        speed.toggle_pause();
+        assert!(!speed.should_run_frame());
+        assert!(!speed.is_normal_speed());
+
+        speed.frame_advance();
+        assert!(speed.should_run_frame());
+        assert!(!speed.should_run_frame());
+
+        speed.toggle_pause();
+        assert!(speed.should_run_frame());
+    }
+}
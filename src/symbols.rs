@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+// Labels from a symbol file, keyed by bank and address. Reads the RGBDS and
+// no$gmb format, one `bank:addr label` per line with `;` comments, and
+// WLA-DX's, which is the same inside a [labels] section among others.
+#[derive(Default)]
+pub struct Symbols {
+    labels: HashMap<(u16, u16), String>,
+    addresses: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    // Lines that don't parse are skipped, as they tend to be extensions
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Self::default();
+        let mut in_labels = true;
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if let Some(section) = line.strip_prefix('[') {
+                in_labels = section.trim_end_matches(']').eq_ignore_ascii_case("labels");
+                continue;
+            }
+            if !in_labels {
+                continue;
+            }
+            if let Some((bank, addr, label)) = parse_line(line) {
+                symbols.insert(bank, addr, label);
+            }
+        }
+        symbols
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    // The symbol file that goes with a ROM: game.sym for game.gb, or failing
+    // that game.gb.sym. Returns the path along with the symbols.
+    pub fn for_rom(rom_path: &str) -> Option<(String, Self)> {
+        let rom_path = Path::new(rom_path);
+        let mut with_suffix = rom_path.as_os_str().to_owned();
+        with_suffix.push(".sym");
+        [rom_path.with_extension("sym"), with_suffix.into()]
+            .into_iter()
+            .find_map(|path| {
+                let symbols = Self::load(&path).ok()?;
+                Some((path.display().to_string(), symbols))
+            })
+    }
+
+    fn insert(&mut self, bank: u16, addr: u16, label: &str) {
+        // Where several labels share an address, the first one names it
+        self.labels
+            .entry((bank, addr))
+            .or_insert_with(|| label.to_string());
+        self.addresses.insert(label.to_string(), (bank, addr));
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    // The label at `addr` with `bank` mapped there. Outside switchable ROM,
+    // a label filed under bank 0 also matches, since assemblers disagree on
+    // the bank numbers of RAM.
+    pub fn label(&self, bank: u16, addr: u16) -> Option<&str> {
+        self.labels
+            .get(&(bank, addr))
+            .or_else(|| match addr {
+                0x4000..=0x7FFF => None,
+                _ => self.labels.get(&(0, addr)),
+            })
+            .map(String::as_str)
+    }
+
+    // The bank and address of a label
+    pub fn address(&self, label: &str) -> Option<(u16, u16)> {
+        self.addresses.get(label).copied()
+    }
+}
+
+// "bank:addr label", both numbers in hex
+fn parse_line(line: &str) -> Option<(u16, u16, &str)> {
+    let (location, label) = line.split_once(char::is_whitespace)?;
+    let (bank, addr) = location.split_once(':')?;
+    let label = label.trim();
+    if label.is_empty() {
+        return None;
+    }
+    Some((
+        u16::from_str_radix(bank, 16).ok()?,
+        u16::from_str_radix(addr, 16).ok()?,
+        label,
+    ))
+}
@@ -0,0 +1,238 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::audio::CYCLES_PER_FRAME;
+use crate::serial::LinkDevice;
+
+// How often each side tells the other how far it has got, and how far ahead
+// of the other it may run before waiting. Transfers themselves always wait
+// for the other side, so this only has to keep the two roughly in step.
+const SYNC_INTERVAL: u32 = 4096; // One byte's transfer time at 8192 Hz
+const MAX_LEAD: u64 = CYCLES_PER_FRAME as u64;
+
+// Longest to wait for the other side at once while ahead of it, or for the
+// reply to a transfer, so a paused or stalled peer slows us down instead of
+// freezing the window
+const SYNC_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Messages between the two emulators. Each is a one byte tag followed by
+// its value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Message {
+    Data(u8),  // The sender clocked a transfer with this byte
+    Reply(u8), // Answer to Data with the byte the receiver had in SB
+    Sync(u64), // Total cycles the sender has run
+}
+
+impl Message {
+    fn encode(self) -> Vec<u8> {
+        match self {
+            Message::Data(byte) => vec![b'D', byte],
+            Message::Reply(byte) => vec![b'R', byte],
+            Message::Sync(cycles) => {
+                let mut bytes = vec![b'S'];
+                bytes.extend_from_slice(&cycles.to_be_bytes());
+                bytes
+            }
+        }
+    }
+
+    fn read(stream: &mut impl Read) -> std::io::Result<Self> {
+        let mut tag = [0u8; 1];
+        stream.read_exact(&mut tag)?;
+        match tag[0] {
+            b'D' | b'R' => {
+                let mut byte = [0u8; 1];
+                stream.read_exact(&mut byte)?;
+                Ok(if tag[0] == b'D' {
+                    Message::Data(byte[0])
+                } else {
+                    Message::Reply(byte[0])
+                })
+            }
+            b'S' => {
+                let mut cycles = [0u8; 8];
+                stream.read_exact(&mut cycles)?;
+                Ok(Message::Sync(u64::from_be_bytes(cycles)))
+            }
+            tag => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Unknown link message 0x{:02X}", tag),
+            )),
+        }
+    }
+}
+
+// A link cable to another rustboy over TCP. The side that clocks a transfer
+// sends how far it has got and its byte, then waits for the other side. That
+// answers with its SB as soon as it is waiting on the external clock, or once
+// it has caught up with the sender without having started a transfer. If
+// both clock at once, each gets 0xFF as if nothing was listening.
+pub struct TcpLink {
+    stream: Option<TcpStream>, // None once the other side has gone
+    incoming: Receiver<Message>,
+    pending_data: Option<u8>, // Data the other side sent that we haven't answered yet
+    cycles: u64,
+    peer_cycles: u64,
+    cycles_since_sync: u32,
+}
+
+impl TcpLink {
+    // Wait for the other emulator to connect to `address`, as host:port
+    pub fn listen(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        log::info!(target: "serial", "Waiting for a link connection on {}", address);
+        let (stream, peer) = listener.accept()?;
+        log::info!(target: "serial", "Link connected to {}", peer);
+        Self::new(stream)
+    }
+
+    pub fn connect(address: &str) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        log::info!(target: "serial", "Link connected to {}", address);
+        Self::new(stream)
+    }
+
+    pub fn new(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_nodelay(true)?;
+
+        // Read on a separate thread so we can check for messages without blocking
+        let mut reader = stream.try_clone()?;
+        let (sender, incoming) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(message) = Message::read(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            stream: Some(stream),
+            incoming,
+            pending_data: None,
+            cycles: 0,
+            peer_cycles: 0,
+            cycles_since_sync: 0,
+        })
+    }
+
+    fn send(&mut self, message: Message) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        if let Err(e) = stream.write_all(&message.encode()) {
+            self.disconnect(&e.to_string());
+        }
+    }
+
+    // Wait up to `timeout` for the next message, keeping track of the other
+    // side's cycle count. None on timeout or once disconnected.
+    fn receive(&mut self, timeout: Duration) -> Option<Message> {
+        self.stream.as_ref()?;
+        match self.incoming.recv_timeout(timeout) {
+            Ok(message) => {
+                if let Message::Sync(cycles) = message {
+                    self.peer_cycles = cycles;
+                }
+                Some(message)
+            }
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.disconnect("connection closed");
+                None
+            }
+        }
+    }
+
+    // Handle whatever has already arrived without waiting
+    fn poll(&mut self) {
+        while let Some(message) = self.receive(Duration::ZERO) {
+            match message {
+                Message::Data(byte) => self.pending_data = Some(byte),
+                // Nothing is waiting on a reply outside `transfer`
+                Message::Reply(_) | Message::Sync(_) => {}
+            }
+        }
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        if self.stream.take().is_some() {
+            log::warn!(target: "serial", "Link disconnected: {}", reason);
+        }
+    }
+}
+
+impl LinkDevice for TcpLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.poll();
+        if self.stream.is_none() {
+            return 0xFF;
+        }
+
+        // The other side already clocked a transfer of its own
+        if self.pending_data.take().is_some() {
+            self.send(Message::Reply(0xFF));
+            return 0xFF;
+        }
+
+        self.send(Message::Sync(self.cycles));
+        self.send(Message::Data(byte));
+
+        // No reply in time reads as nothing connected. A late one is dropped.
+        let deadline = Instant::now() + SYNC_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.receive(timeout) {
+                Some(Message::Reply(received)) => return received,
+                // Both clocked at once: answer theirs with nothing and keep
+                // waiting, they'll do the same for ours
+                Some(Message::Data(_)) => self.send(Message::Reply(0xFF)),
+                Some(Message::Sync(_)) => {}
+                None => return 0xFF,
+            }
+        }
+    }
+
+    fn external_transfer(&mut self, byte: u8) -> Option<u8> {
+        self.poll();
+        let received = self.pending_data.take()?;
+        self.send(Message::Reply(byte));
+        Some(received)
+    }
+
+    // Once we've run as far as the other side had when it clocked its
+    // transfer, we weren't going to join in. It gets our SB, and ours stays
+    // as it was.
+    fn idle(&mut self, sb: u8) {
+        self.poll();
+        if self.pending_data.is_some() && self.cycles >= self.peer_cycles {
+            self.pending_data = None;
+            self.send(Message::Reply(sb));
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+        self.cycles_since_sync += cycles;
+        if self.cycles_since_sync >= SYNC_INTERVAL {
+            self.cycles_since_sync = 0;
+            self.send(Message::Sync(self.cycles));
+        }
+
+        // Too far ahead: wait for the other side to catch up, unless it is
+        // already waiting on us to answer a transfer
+        while self.stream.is_some()
+            && self.pending_data.is_none()
+            && self.cycles > self.peer_cycles + MAX_LEAD
+        {
+            match self.receive(SYNC_TIMEOUT) {
+                Some(Message::Data(byte)) => self.pending_data = Some(byte),
+                Some(_) => {}
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::cpu::Registers;
+use crate::disasm;
+use crate::mmu::MMU;
+use crate::symbols::Symbols;
+
+// An inclusive range of addresses to trace, written "start-end" in hex
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PcRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl std::str::FromStr for PcRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |addr: &str| u16::from_str_radix(addr.trim().trim_start_matches("0x"), 16);
+        s.split_once('-')
+            .and_then(|(start, end)| Some((parse(start).ok()?, parse(end).ok()?)))
+            .filter(|(start, end)| start <= end)
+            .map(|(start, end)| PcRange { start, end })
+            .ok_or_else(|| format!("Invalid address range: {}", s))
+    }
+}
+
+// One traced instruction: the registers before it ran, the bank it ran from
+// and the four bytes at PC
+#[derive(Clone, Copy, Debug)]
+pub struct TraceEntry {
+    pub registers: Registers,
+    pub bank: u16,
+    pub pcmem: [u8; 4],
+}
+
+// The Gameboy Doctor line format, so traces can be diffed against its logs
+// and other emulators' that write it. It's the only format; BGB-style logs
+// aren't supported.
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = &self.registers;
+        let [m0, m1, m2, m3] = self.pcmem;
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} \
+             SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, r.pc, m0, m1, m2, m3
+        )
+    }
+}
+
+// Instruction tracing: every instruction the CPU runs can be written to a
+// log, and the last few kept in memory to dump when something goes wrong.
+// The range and bank filters only apply to the log; the history keeps
+// everything leading up to a crash.
+#[derive(Default)]
+pub struct Trace {
+    output: Option<Box<dyn Write>>,
+    pub range: Option<PcRange>, // Only log instructions in this range
+    pub bank: Option<u16>,      // Only log instructions run from this bank
+    history: VecDeque<TraceEntry>,
+    history_len: usize, // 0 to keep no history
+}
+
+impl Trace {
+    pub fn log_to_file(&mut self, path: &str) -> std::io::Result<()> {
+        self.log_to(Box::new(BufWriter::new(File::create(path)?)));
+        log::info!("Tracing instructions to {}", path);
+        Ok(())
+    }
+
+    pub fn log_to(&mut self, output: Box<dyn Write>) {
+        self.output = Some(output);
+    }
+
+    // Keep the last `len` instructions, to dump on a crash or unknown opcode
+    pub fn keep_history(&mut self, len: usize) {
+        self.history_len = len;
+        while self.history.len() > len {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.output.is_some() || self.history_len > 0
+    }
+
+    // Called before executing the instruction at PC
+    pub fn record(&mut self, registers: &Registers, mmu: &MMU) {
+        if !self.is_enabled() {
+            return;
+        }
+        let pc = registers.pc;
+        let entry = TraceEntry {
+            registers: *registers,
+            bank: mmu.bank_at(pc),
+            pcmem: std::array::from_fn(|i| mmu.peek_byte(pc.wrapping_add(i as u16))),
+        };
+
+        if self.history_len > 0 {
+            if self.history.len() == self.history_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(entry);
+        }
+
+        let in_range = self
+            .range
+            .is_none_or(|range| (range.start..=range.end).contains(&pc));
+        let in_bank = self.bank.is_none_or(|bank| bank == entry.bank);
+        if let Some(output) = self.output.as_mut().filter(|_| in_range && in_bank) {
+            if let Err(e) = writeln!(output, "{}", entry) {
+                log::error!("Failed to write the trace, stopping it: {}", e);
+                self.output = None;
+            }
+        }
+    }
+
+    // The last instructions run, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.history.iter()
+    }
+
+    // Print the history to stderr, each line with its disassembly
+    pub fn dump(&self, reason: &str, symbols: &Symbols) {
+        let _ = self.dump_to(&mut std::io::stderr(), reason, symbols);
+    }
+
+    // Write the history, each line with its disassembly. Labels go on lines
+    // of their own before the instructions they're on, and replace the
+    // operands they name.
+    pub fn dump_to(
+        &self,
+        out: &mut dyn Write,
+        reason: &str,
+        symbols: &Symbols,
+    ) -> std::io::Result<()> {
+        if self.history.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            out,
+            "{}; the last {} instructions:",
+            reason,
+            self.history.len()
+        )?;
+        for entry in &self.history {
+            let pc = entry.registers.pc;
+            if let Some(label) = symbols.label(entry.bank, pc) {
+                writeln!(out, "{}:", label)?;
+            }
+            let instruction = disasm::disassemble(&entry.pcmem, pc);
+            let operand = instruction
+                .target
+                .and_then(|target| symbols.label(target_bank(entry, target), target));
+            writeln!(
+                out,
+                "{}  {:02X}  {}",
+                entry,
+                entry.bank,
+                instruction.labelled(operand)
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(output) = self.output.as_mut() {
+            if let Err(e) = output.flush() {
+                log::error!("Failed to write the trace: {}", e);
+            }
+        }
+    }
+}
+
+// Only the bank PC ran from is recorded, so a target in the same 16KB is
+// taken to be in that bank. Elsewhere bank 0 stands in, which labels
+// outside switchable ROM fall back to.
+fn target_bank(entry: &TraceEntry, target: u16) -> u16 {
+    if target & 0xC000 == entry.registers.pc & 0xC000 {
+        entry.bank
+    } else {
+        0
+    }
+}
+
+impl Drop for Trace {
+    // Unwinding from a panic drops the machine, which is the last chance to
+    // show what led up to it. The symbols have already gone by then.
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.dump("Crashed", &Symbols::default());
+        }
+        self.flush();
+    }
+}
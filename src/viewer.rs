@@ -0,0 +1,115 @@
+extern crate sdl2;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Canvas, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use sdl2::VideoSubsystem;
+
+// Scale from image pixels to window pixels
+const SCALE: u32 = 3;
+
+// The debug windows that can be opened alongside the game
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewerKind {
+    Tiles,   // Everything in VRAM's tile data
+    Maps,    // Both background tile maps
+    Sprites, // Every OAM entry
+}
+
+// A debug window showing some part of the machine as an image. It keeps
+// track of the mouse so whoever fills it in can say what's under it.
+pub struct Viewer {
+    pub kind: ViewerKind,
+    canvas: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    width: u32, // Of the image, in its own pixels
+    height: u32,
+    mouse: Option<(u32, u32)>, // In image pixels
+    clicked: bool,             // Left button pressed since the last `take_click`
+    scrolled: i32,             // Wheel clicks up since the last `take_scroll`, less those down
+}
+
+impl Viewer {
+    pub fn new(
+        video: &VideoSubsystem,
+        kind: ViewerKind,
+        title: &str,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, String> {
+        let window = video
+            .window(title, width * SCALE, height * SCALE)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        let texture_creator = canvas.texture_creator();
+        Ok(Self {
+            kind,
+            canvas,
+            texture_creator,
+            width,
+            height,
+            mouse: None,
+            clicked: false,
+            scrolled: 0,
+        })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // Follow the mouse in this window
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::MouseMotion { x, y, .. } => {
+                let (x, y) = (x.max(0) as u32 / SCALE, y.max(0) as u32 / SCALE);
+                self.mouse = Some((x, y)).filter(|_| x < self.width && y < self.height);
+            }
+            Event::Window {
+                win_event: WindowEvent::Leave,
+                ..
+            } => self.mouse = None,
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => self.clicked = true,
+            Event::MouseWheel { y, .. } => self.scrolled += y,
+            _ => {}
+        }
+    }
+
+    // The image pixel under the mouse, if it's over the window
+    pub fn mouse(&self) -> Option<(u32, u32)> {
+        self.mouse
+    }
+
+    pub fn take_click(&mut self) -> bool {
+        std::mem::take(&mut self.clicked)
+    }
+
+    pub fn take_scroll(&mut self) -> i32 {
+        std::mem::take(&mut self.scrolled)
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    // Show an image of the window's size, 3 bytes (RGB) per pixel
+    pub fn show(&mut self, pixels: &[u8]) {
+        let Ok(mut texture) = self.texture_creator.create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            self.width,
+            self.height,
+        ) else {
+            return;
+        };
+        let _ = texture.update(None, pixels, self.width as usize * 3);
+        self.canvas.clear();
+        let _ = self.canvas.copy(&texture, None, None);
+        self.canvas.present();
+    }
+}
@@ -0,0 +1,441 @@
+use std::fmt;
+
+use crate::mmu::MMU;
+use crate::ppu::{decode_tile, Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Tile data fills 0x8000-0x97FF of each VRAM bank
+pub const TILES_PER_BANK: u16 = 384;
+
+// The tile sheet lays each bank out 16 tiles wide and 24 high
+const SHEET_COLUMNS: u32 = 16;
+const SHEET_ROWS: u32 = TILES_PER_BANK as u32 / SHEET_COLUMNS;
+
+// How to colour tiles when viewing them outside of a tile map or sprite
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TilePalette {
+    Raw,  // Color IDs as they are, 0 lightest
+    Bgp,  // Through the background palette, 0xFF47
+    Obp0, // Through object palette 0, 0xFF48
+    Obp1, // Through object palette 1, 0xFF49
+}
+
+impl TilePalette {
+    pub fn next(self) -> Self {
+        match self {
+            TilePalette::Raw => TilePalette::Bgp,
+            TilePalette::Bgp => TilePalette::Obp0,
+            TilePalette::Obp0 => TilePalette::Obp1,
+            TilePalette::Obp1 => TilePalette::Raw,
+        }
+    }
+
+    // The colour of each color ID, in the shades of the model's screen
+    pub fn colors(self, mmu: &MMU) -> [(u8, u8, u8); 4] {
+        let shades = Ppu::palette_for(mmu.model, mmu.cgb_mode);
+        let register = match self {
+            TilePalette::Raw => 0xE4, // 3, 2, 1, 0: every ID its own shade
+            TilePalette::Bgp => mmu.memory[0xFF47],
+            TilePalette::Obp0 => mmu.memory[0xFF48],
+            TilePalette::Obp1 => mmu.memory[0xFF49],
+        };
+        std::array::from_fn(|id| shades[(register >> (id * 2)) as usize & 0x03])
+    }
+}
+
+impl fmt::Display for TilePalette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TilePalette::Raw => "raw",
+            TilePalette::Bgp => "BGP",
+            TilePalette::Obp0 => "OBP0",
+            TilePalette::Obp1 => "OBP1",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// One of the tiles in VRAM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub bank: u8,
+    pub number: u16, // 0-383, in address order
+}
+
+impl Tile {
+    pub fn address(self) -> u16 {
+        0x8000 + self.number * 16
+    }
+
+    // What a tile map or sprite uses to pick the tile. Tiles 0-255 are
+    // indexed from 0x8000 and 128-383 from 0x8800 with 0x9000 as index 0,
+    // so the low byte works either way.
+    pub fn index(self) -> u8 {
+        self.number as u8
+    }
+}
+
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tile {:03X} index {:02X} at {}:{:04X}",
+            self.number,
+            self.index(),
+            self.bank,
+            self.address()
+        )
+    }
+}
+
+// Every tile in VRAM drawn as one image, with the CGB's second bank to the
+// right of the first
+pub struct TileSheet {
+    banks: u8,
+}
+
+impl TileSheet {
+    pub fn new(mmu: &MMU) -> Self {
+        Self {
+            banks: if mmu.model.is_cgb() { 2 } else { 1 },
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        SHEET_COLUMNS * 8 * self.banks as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        SHEET_ROWS * 8
+    }
+
+    // 3 bytes (RGB) per pixel, row by row
+    pub fn render(&self, mmu: &MMU, palette: TilePalette) -> Vec<u8> {
+        let colors = palette.colors(mmu);
+        let width = self.width() as usize;
+        let mut pixels = vec![0; width * self.height() as usize * 3];
+        for bank in 0..self.banks {
+            for number in 0..TILES_PER_BANK {
+                let tile = Tile { bank, number };
+                let (x, y) = self.position(tile);
+                let ids = decode_tile(&mmu.tile_data_in_bank(bank, number));
+                for (ty, row) in ids.iter().enumerate() {
+                    for (tx, &id) in row.iter().enumerate() {
+                        let (x, y) = (x as usize + tx, y as usize + ty);
+                        set_pixel(&mut pixels, width, x, y, colors[id as usize]);
+                    }
+                }
+            }
+        }
+        pixels
+    }
+
+    // The top left pixel of a tile on the sheet
+    pub fn position(&self, tile: Tile) -> (u32, u32) {
+        let column = tile.number as u32 % SHEET_COLUMNS + tile.bank as u32 * SHEET_COLUMNS;
+        let row = tile.number as u32 / SHEET_COLUMNS;
+        (column * 8, row * 8)
+    }
+
+    // The tile under a pixel of the sheet
+    pub fn tile_at(&self, x: u32, y: u32) -> Option<Tile> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let column = x / 8;
+        Some(Tile {
+            bank: (column / SHEET_COLUMNS) as u8,
+            number: ((y / 8) * SHEET_COLUMNS + column % SHEET_COLUMNS) as u16,
+        })
+    }
+}
+
+// Tile maps are 32x32 tiles, 256x256 pixels
+pub const MAP_TILES: u8 = 32;
+pub const MAP_SIZE: u32 = MAP_TILES as u32 * 8;
+
+// LCDC bits that pick where the background and window come from
+const LCDC_BG_MAP: u8 = 0x08;
+const LCDC_TILE_DATA: u8 = 0x10;
+const LCDC_WINDOW_ENABLE: u8 = 0x20;
+const LCDC_WINDOW_MAP: u8 = 0x40;
+
+// Drawn over the map viewer: the part of the background on screen, and
+// where the window starts
+const VIEWPORT_COLOR: (u8, u8, u8) = (255, 0, 0);
+const WINDOW_COLOR: (u8, u8, u8) = (0, 0, 255);
+
+// One of the two tile maps in VRAM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileMap {
+    Low,  // 0x9800-0x9BFF
+    High, // 0x9C00-0x9FFF
+}
+
+impl TileMap {
+    pub fn address(self) -> u16 {
+        match self {
+            TileMap::Low => 0x9800,
+            TileMap::High => 0x9C00,
+        }
+    }
+
+    // The map LCDC bit 3 has the background drawn from
+    pub fn background(mmu: &MMU) -> Self {
+        Self::selected(mmu, LCDC_BG_MAP)
+    }
+
+    // The map LCDC bit 6 has the window drawn from
+    pub fn window(mmu: &MMU) -> Self {
+        Self::selected(mmu, LCDC_WINDOW_MAP)
+    }
+
+    fn selected(mmu: &MMU, bit: u8) -> Self {
+        if mmu.memory[0xFF40] & bit != 0 {
+            TileMap::High
+        } else {
+            TileMap::Low
+        }
+    }
+
+    // The cell `x` tiles across and `y` down, both 0-31
+    pub fn cell(self, mmu: &MMU, x: u8, y: u8) -> MapCell {
+        let offset = y as usize * MAP_TILES as usize + x as usize;
+        let address = self.address() as usize + offset;
+        MapCell {
+            map: self,
+            x,
+            y,
+            index: mmu.memory[address],
+            // Only CGB games have attributes, in the second VRAM bank
+            attributes: mmu
+                .cgb_mode
+                .then(|| MapAttributes(mmu.vram_bank1[address - 0x8000])),
+        }
+    }
+
+    // The color ID of every pixel of the map, 256 across and 256 down
+    pub fn color_ids(self, mmu: &MMU) -> Vec<u8> {
+        let size = MAP_SIZE as usize;
+        let mut ids = vec![0; size * size];
+        for y in 0..MAP_TILES {
+            for x in 0..MAP_TILES {
+                let tile = self.cell(mmu, x, y).color_ids(mmu);
+                for (ty, row) in tile.iter().enumerate() {
+                    let start = (y as usize * 8 + ty) * size + x as usize * 8;
+                    ids[start..start + 8].copy_from_slice(row);
+                }
+            }
+        }
+        ids
+    }
+}
+
+impl fmt::Display for TileMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X}", self.address())
+    }
+}
+
+// A CGB tile map attribute byte, held in VRAM bank 1 behind the tile index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapAttributes(pub u8);
+
+impl MapAttributes {
+    pub fn palette(self) -> u8 {
+        self.0 & 0x07
+    }
+
+    pub fn bank(self) -> u8 {
+        (self.0 >> 3) & 1
+    }
+
+    pub fn x_flip(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    pub fn y_flip(self) -> bool {
+        self.0 & 0x40 != 0
+    }
+
+    // Drawn over sprites whatever their own priority says
+    pub fn priority(self) -> bool {
+        self.0 & 0x80 != 0
+    }
+}
+
+impl fmt::Display for MapAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "palette {}, bank {}", self.palette(), self.bank())?;
+        if self.x_flip() {
+            write!(f, ", X flip")?;
+        }
+        if self.y_flip() {
+            write!(f, ", Y flip")?;
+        }
+        if self.priority() {
+            write!(f, ", priority")?;
+        }
+        Ok(())
+    }
+}
+
+// One tile's worth of a tile map
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapCell {
+    pub map: TileMap,
+    pub x: u8, // In tiles, 0-31
+    pub y: u8,
+    pub index: u8,
+    pub attributes: Option<MapAttributes>, // CGB games only
+}
+
+impl MapCell {
+    pub fn address(self) -> u16 {
+        self.map.address() + self.y as u16 * MAP_TILES as u16 + self.x as u16
+    }
+
+    // The tile the index picks. With LCDC bit 4 set indexes count from
+    // 0x8000, otherwise they're signed and count from 0x9000.
+    pub fn tile(self, mmu: &MMU) -> Tile {
+        let number = if mmu.memory[0xFF40] & LCDC_TILE_DATA != 0 || self.index >= 0x80 {
+            self.index as u16
+        } else {
+            0x100 + self.index as u16
+        };
+        Tile {
+            bank: self.attributes.map_or(0, MapAttributes::bank),
+            number,
+        }
+    }
+
+    // The color IDs of the cell's pixels, flipped as its attributes say
+    pub fn color_ids(self, mmu: &MMU) -> [[u8; 8]; 8] {
+        let tile = self.tile(mmu);
+        let mut ids = decode_tile(&mmu.tile_data_in_bank(tile.bank, tile.number));
+        if let Some(attributes) = self.attributes {
+            if attributes.x_flip() {
+                ids.iter_mut().for_each(|row| row.reverse());
+            }
+            if attributes.y_flip() {
+                ids.reverse();
+            }
+        }
+        ids
+    }
+}
+
+impl fmt::Display for MapCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "map {} cell {},{} at {:04X}, index {:02X}",
+            self.map,
+            self.x,
+            self.y,
+            self.address(),
+            self.index
+        )?;
+        if let Some(attributes) = self.attributes {
+            write!(f, ", {}", attributes)?;
+        }
+        Ok(())
+    }
+}
+
+// Both tile maps drawn in full, 0x9800 on the left and 0x9C00 on the
+// right, with the screen's view of the background marked on top
+pub struct MapSheet;
+
+impl MapSheet {
+    pub fn width(&self) -> u32 {
+        MAP_SIZE * 2
+    }
+
+    pub fn height(&self) -> u32 {
+        MAP_SIZE
+    }
+
+    // 3 bytes (RGB) per pixel, row by row
+    pub fn render(&self, mmu: &MMU, palette: TilePalette) -> Vec<u8> {
+        let colors = palette.colors(mmu);
+        let width = self.width() as usize;
+        let mut pixels = vec![0; width * self.height() as usize * 3];
+        for (i, map) in [TileMap::Low, TileMap::High].into_iter().enumerate() {
+            let ids = map.color_ids(mmu);
+            for (y, row) in ids.chunks(MAP_SIZE as usize).enumerate() {
+                for (x, &id) in row.iter().enumerate() {
+                    let x = i * MAP_SIZE as usize + x;
+                    set_pixel(&mut pixels, width, x, y, colors[id as usize]);
+                }
+            }
+        }
+        self.draw_overlay(mmu, &mut pixels);
+        pixels
+    }
+
+    // Outline the 160x144 pixels of the background the screen shows at
+    // SCX/SCY, wrapping around the map's edges, and put a cross where the
+    // window's top left corner lands on it
+    fn draw_overlay(&self, mmu: &MMU, pixels: &mut [u8]) {
+        let width = self.width() as usize;
+        let left = if TileMap::background(mmu) == TileMap::High {
+            MAP_SIZE as usize
+        } else {
+            0
+        };
+        let (scx, scy) = (mmu.memory[0xFF43], mmu.memory[0xFF42]);
+        let mut mark = |dx: u32, dy: u32, color| {
+            let x = (scx as u32 + dx) % MAP_SIZE;
+            let y = (scy as u32 + dy) % MAP_SIZE;
+            set_pixel(pixels, width, x as usize + left, y as usize, color);
+        };
+        for dx in 0..SCREEN_WIDTH {
+            mark(dx, 0, VIEWPORT_COLOR);
+            mark(dx, SCREEN_HEIGHT - 1, VIEWPORT_COLOR);
+        }
+        for dy in 0..SCREEN_HEIGHT {
+            mark(0, dy, VIEWPORT_COLOR);
+            mark(SCREEN_WIDTH - 1, dy, VIEWPORT_COLOR);
+        }
+
+        // Going back 2 pixels is going forward 254 around the map
+        if let Some((x, y)) = window_origin(mmu) {
+            for d in 0..5 {
+                mark(x + d + MAP_SIZE - 2, y, WINDOW_COLOR);
+                mark(x, y + d + MAP_SIZE - 2, WINDOW_COLOR);
+            }
+        }
+    }
+
+    // The map cell under a pixel of the sheet
+    pub fn cell_at(&self, mmu: &MMU, x: u32, y: u32) -> Option<MapCell> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let map = if x < MAP_SIZE {
+            TileMap::Low
+        } else {
+            TileMap::High
+        };
+        Some(map.cell(mmu, (x % MAP_SIZE / 8) as u8, (y / 8) as u8))
+    }
+}
+
+// Where on the screen the window starts, if it's enabled and on screen.
+// WX is the window's left edge plus 7.
+pub fn window_origin(mmu: &MMU) -> Option<(u32, u32)> {
+    let (wx, wy) = (mmu.memory[0xFF4B] as u32, mmu.memory[0xFF4A] as u32);
+    let enabled = mmu.memory[0xFF40] & LCDC_WINDOW_ENABLE != 0;
+    (enabled && wx < SCREEN_WIDTH + 7 && wy < SCREEN_HEIGHT).then(|| (wx.saturating_sub(7), wy))
+}
+
+pub(crate) fn set_pixel(
+    pixels: &mut [u8],
+    width: usize,
+    x: usize,
+    y: usize,
+    (r, g, b): (u8, u8, u8),
+) {
+    let i = (y * width + x) * 3;
+    pixels[i..i + 3].copy_from_slice(&[r, g, b]);
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+// The RIFF size field holds the data size plus the 36 bytes of header after
+// it, so that's all the data a WAV file can have
+const MAX_DATA_BYTES: u32 = u32::MAX - 36;
+
+// Minimal 16-bit PCM WAV writer. The RIFF and data chunk sizes are written as
+// placeholders and patched in `finish` once the final length is known.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    data_bytes: u32,
+    block_align: u16,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, sample_rate: u32, channels: u16) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let block_align = channels * 2; // 2 bytes per sample
+        let byte_rate = sample_rate * block_align as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?; // RIFF size, patched in finish()
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?; // Bits per sample
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?; // Data size, patched in finish()
+
+        Ok(Self {
+            writer,
+            data_bytes: 0,
+            block_align,
+            finished: false,
+        })
+    }
+
+    // Write interleaved samples (L, R, L, R, ... for stereo). Once the file
+    // is as big as a WAV file can be, the whole frames that fit are written
+    // and the rest is an error.
+    pub fn write_samples(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        let room = MAX_DATA_BYTES - self.data_bytes;
+        let room = room - room % self.block_align as u32;
+        let fits = samples.len().min(room as usize / 2);
+        for sample in &samples[..fits] {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_bytes += fits as u32 * 2;
+        if fits < samples.len() {
+            return Err(std::io::Error::other("WAV file is full at 4 GiB"));
+        }
+        Ok(())
+    }
+
+    // Patch the chunk sizes in the header and flush everything to disk
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(36 + self.data_bytes).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_bytes.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        // Make sure a recording cut short still produces a valid file
+        let _ = self.finish();
+    }
+}
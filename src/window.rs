@@ -0,0 +1,367 @@
+use crate::debugger::{Debugging, Resume};
+use crate::input::Hotkey;
+use crate::options::Options;
+use crate::viewer::{Viewer, ViewerKind};
+use crate::{graphics, input, speed};
+use crate::{
+    save_screenshot, save_screenshot_at_frame, start_audio_recording, start_trace, timestamped_path,
+};
+use rustboy::local_link::LinkedPair;
+use rustboy::oam::{LineSprites, Sprite, SpriteSheet};
+use rustboy::ppu::SCREEN_HEIGHT;
+use rustboy::vram::{MapSheet, TilePalette, TileSheet};
+use rustboy::{audio, cpu, gameboy, gbs, mmu, model, rewind};
+
+// Run a game in an SDL window until the window is closed
+pub fn run(mut gameboy: gameboy::GameBoy, options: &Options) {
+    let mut graphics = graphics::Graphics::new();
+    let mut input = input::Input::new();
+    start_audio_recording(&mut gameboy.audio, options);
+    start_trace(&mut gameboy, options);
+
+    let mut rewind = rewind::Rewind::new(options.rewind_seconds, options.rewind_interval);
+
+    let mut speed = speed::SpeedControl::new();
+    let mut frames = 0;
+    save_screenshot_at_frame(&gameboy, frames, options);
+
+    let mut debugging = Debugging::new(options);
+    let mut break_in = false;
+    let mut tile_palette = TilePalette::Raw;
+    let mut map_palette = TilePalette::Raw;
+    let mut sprite_line = 0;
+    if options.tile_viewer {
+        input.press_hotkey(Hotkey::ToggleTileViewer);
+    }
+    if options.map_viewer {
+        input.press_hotkey(Hotkey::ToggleMapViewer);
+    }
+    if options.sprite_viewer {
+        input.press_hotkey(Hotkey::ToggleSpriteViewer);
+    }
+    if debugging.start(&mut gameboy) == Resume::Quit {
+        return;
+    }
+
+    // Main emulation loop
+    loop {
+        // Handle events (quit if needed)
+        if graphics.handle_events(&mut input) {
+            break; // Exit the loop if the user closes the window
+        }
+
+        for hotkey in input.poll() {
+            match hotkey {
+                Hotkey::ToggleAudioRecording => toggle_audio_recording(&mut gameboy.audio, options),
+                Hotkey::SaveState(slot) => {
+                    let path = gameboy::state_slot_path(&options.rom_path, slot);
+                    match gameboy.save_state_to_file(&path) {
+                        Ok(()) => println!("Saved state to slot {}", slot),
+                        Err(e) => println!("Failed to save state: {}", e),
+                    }
+                }
+                Hotkey::LoadState(slot) => {
+                    let path = gameboy::state_slot_path(&options.rom_path, slot);
+                    match gameboy.load_state_from_file(&path) {
+                        Ok(()) => println!("Loaded state from slot {}", slot),
+                        Err(e) => println!("Failed to load state: {}", e),
+                    }
+                }
+                Hotkey::Screenshot => save_screenshot(&gameboy, &timestamped_path("png"), options),
+                Hotkey::Debug => break_in = true,
+                Hotkey::ToggleTileViewer => {
+                    let sheet = TileSheet::new(&gameboy.mmu);
+                    graphics.toggle_viewer(
+                        ViewerKind::Tiles,
+                        "VRAM tiles",
+                        sheet.width(),
+                        sheet.height(),
+                    );
+                }
+                Hotkey::ToggleMapViewer => graphics.toggle_viewer(
+                    ViewerKind::Maps,
+                    "Tile maps",
+                    MapSheet.width(),
+                    MapSheet.height(),
+                ),
+                Hotkey::ToggleSpriteViewer => graphics.toggle_viewer(
+                    ViewerKind::Sprites,
+                    "Sprites",
+                    SpriteSheet.width(),
+                    SpriteSheet.height(),
+                ),
+                Hotkey::TogglePause | Hotkey::FrameAdvance | Hotkey::SpeedUp | Hotkey::SlowDown => {
+                    speed.handle_hotkey(hotkey)
+                }
+                Hotkey::NextTrack | Hotkey::PreviousTrack | Hotkey::Rewind | Hotkey::Turbo => {}
+            }
+        }
+        speed.set_turbo(input.is_held(Hotkey::Turbo));
+        gameboy.set_buttons(input.buttons(0));
+
+        // The window stops responding while the debugger has the terminal
+        if std::mem::take(&mut break_in) && debugging.break_in(&mut gameboy) == Resume::Quit {
+            break;
+        }
+
+        // While rewind is held, step back through history instead of running.
+        // Once the history runs out the game stays at the oldest point.
+        if input.is_held(Hotkey::Rewind) {
+            rewind.step_back(&mut gameboy);
+        } else if speed.should_run_frame() {
+            // Audio is muted unless running in real time
+            gameboy.audio.muted = !speed.is_normal_speed();
+            let result = gameboy.run_frame();
+            rewind.record(&gameboy);
+            frames += 1;
+            save_screenshot_at_frame(&gameboy, frames, options);
+
+            if debugging.check(&mut gameboy, result) == Resume::Quit {
+                break;
+            }
+        }
+
+        // Render the graphics to the screen
+        graphics.render(gameboy.framebuffer());
+        if let Some(viewer) = graphics.viewer_mut(ViewerKind::Tiles) {
+            show_tiles(viewer, &gameboy, &mut tile_palette);
+        }
+        if let Some(viewer) = graphics.viewer_mut(ViewerKind::Maps) {
+            show_maps(viewer, &gameboy, &mut map_palette);
+        }
+        if let Some(viewer) = graphics.viewer_mut(ViewerKind::Sprites) {
+            show_sprites(viewer, &gameboy, &mut sprite_line);
+        }
+
+        // Wait for the next frame at the selected speed
+        speed.wait_for_next_frame();
+    }
+
+    // Finalize any recording still in progress
+    gameboy.audio.stop_recording();
+}
+
+// Draw every tile in VRAM and name the one under the mouse. Clicking in the
+// window moves on to the next palette.
+fn show_tiles(viewer: &mut Viewer, gameboy: &gameboy::GameBoy, palette: &mut TilePalette) {
+    if viewer.take_click() {
+        *palette = palette.next();
+    }
+    let sheet = TileSheet::new(&gameboy.mmu);
+    viewer.show(&sheet.render(&gameboy.mmu, *palette));
+
+    let title = match viewer.mouse().and_then(|(x, y)| sheet.tile_at(x, y)) {
+        Some(tile) => format!("VRAM tiles, {} palette: {}", palette, tile),
+        None => format!("VRAM tiles, {} palette", palette),
+    };
+    viewer.set_title(&title);
+}
+
+// Draw both tile maps with the screen's view of the background outlined,
+// and describe the cell under the mouse. Clicking moves on to the next
+// palette.
+fn show_maps(viewer: &mut Viewer, gameboy: &gameboy::GameBoy, palette: &mut TilePalette) {
+    if viewer.take_click() {
+        *palette = palette.next();
+    }
+    let mmu = &gameboy.mmu;
+    viewer.show(&MapSheet.render(mmu, *palette));
+
+    let title = match viewer
+        .mouse()
+        .and_then(|(x, y)| MapSheet.cell_at(mmu, x, y))
+    {
+        Some(cell) => format!(
+            "Tile maps, {} palette: {}, {}",
+            palette,
+            cell,
+            cell.tile(mmu)
+        ),
+        None => format!("Tile maps, {} palette", palette),
+    };
+    viewer.set_title(&title);
+}
+
+// Draw every sprite in OAM, framed by whether it's on a line of the
+// screen, and describe the one under the mouse. Scrolling in the window
+// moves up and down the screen a line at a time.
+fn show_sprites(viewer: &mut Viewer, gameboy: &gameboy::GameBoy, line: &mut u8) {
+    // Scrolling up moves up the screen, wrapping around at either end
+    let lines = SCREEN_HEIGHT as i32;
+    *line = (*line as i32 - viewer.take_scroll()).rem_euclid(lines) as u8;
+    let mmu = &gameboy.mmu;
+    viewer.show(&SpriteSheet.render(mmu, *line));
+
+    let on_line = LineSprites::new(mmu, *line);
+    let title = format!(
+        "Sprites, line {}: {} drawn, {} dropped",
+        line,
+        on_line.drawn.len(),
+        on_line.dropped.len()
+    );
+    let title = match viewer
+        .mouse()
+        .and_then(|(x, y)| SpriteSheet.sprite_at(x, y))
+    {
+        Some(number) => format!("{}: {}", title, Sprite::read(mmu, number)),
+        None => title,
+    };
+    viewer.set_title(&title);
+}
+
+// Run two linked Game Boys in lockstep, both shown at once. Save states,
+// rewind and audio recording only apply to a single Game Boy.
+pub fn run_dual(mut pair: LinkedPair, options: &Options) {
+    let mut graphics = graphics::Graphics::with_screens(2, options.dual_windows);
+    let mut input = input::Input::new();
+    let mut speed = speed::SpeedControl::new();
+
+    loop {
+        if graphics.handle_events(&mut input) {
+            break;
+        }
+
+        for hotkey in input.poll() {
+            match hotkey {
+                Hotkey::Screenshot => {
+                    let path = timestamped_path("png");
+                    let stem = path.strip_suffix(".png").unwrap_or(&path);
+                    for (player, machine) in pair.machines.iter().enumerate() {
+                        let path = format!("{}_{}.png", stem, player + 1);
+                        save_screenshot(machine, &path, options);
+                    }
+                }
+                Hotkey::TogglePause | Hotkey::FrameAdvance | Hotkey::SpeedUp | Hotkey::SlowDown => {
+                    speed.handle_hotkey(hotkey)
+                }
+                Hotkey::ToggleAudioRecording
+                | Hotkey::NextTrack
+                | Hotkey::PreviousTrack
+                | Hotkey::SaveState(_)
+                | Hotkey::LoadState(_)
+                | Hotkey::Rewind
+                | Hotkey::Turbo
+                | Hotkey::Debug
+                | Hotkey::ToggleTileViewer
+                | Hotkey::ToggleMapViewer
+                | Hotkey::ToggleSpriteViewer => {}
+            }
+        }
+        speed.set_turbo(input.is_held(Hotkey::Turbo));
+
+        for (player, machine) in pair.machines.iter_mut().enumerate() {
+            machine.set_buttons(input.buttons(player));
+            machine.audio.muted = !speed.is_normal_speed();
+        }
+        if speed.should_run_frame() {
+            if let Err(e) = pair.run_frame() {
+                log::error!(target: "cpu", "{}", e);
+            }
+        }
+
+        let [first, second] = &pair.machines;
+        graphics.render_screens(&[first.framebuffer(), second.framebuffer()]);
+
+        speed.wait_for_next_frame();
+    }
+}
+
+// Play a GBS music rip: the CPU and audio run as usual, but there is no
+// cartridge or PPU and the player calls the sound driver directly
+pub fn run_gbs_player(options: &Options) {
+    let gbs = match gbs::GbsFile::load(&options.rom_path) {
+        Ok(gbs) => gbs,
+        Err(e) => {
+            println!("Failed to load GBS file: {}", e);
+            return;
+        }
+    };
+
+    println!(
+        "GBS: {} by {} ({}), {} tracks",
+        gbs.title, gbs.author, gbs.copyright, gbs.song_count
+    );
+
+    let model = options.model.unwrap_or(model::Model::Dmg);
+    let mut cpu = cpu::CPU::new(model);
+    let mut mmu = mmu::MMU::new(model);
+    let mut graphics = graphics::Graphics::new();
+    let mut input = input::Input::new();
+    let mut audio = audio::Audio::new(model);
+    start_audio_recording(&mut audio, options);
+
+    let mut player = gbs::GbsPlayer::new(gbs);
+    let track = options.track.map_or(player.track, |t| t.saturating_sub(1));
+    player.start_track(&mut cpu, &mut mmu, track);
+    set_track_title(&mut graphics, &player);
+
+    let mut speed = speed::SpeedControl::new();
+
+    loop {
+        if graphics.handle_events(&mut input) {
+            break;
+        }
+
+        for hotkey in input.poll() {
+            match hotkey {
+                Hotkey::ToggleAudioRecording => toggle_audio_recording(&mut audio, options),
+                Hotkey::NextTrack => {
+                    player.next_track(&mut cpu, &mut mmu);
+                    set_track_title(&mut graphics, &player);
+                }
+                Hotkey::PreviousTrack => {
+                    player.previous_track(&mut cpu, &mut mmu);
+                    set_track_title(&mut graphics, &player);
+                }
+                Hotkey::TogglePause | Hotkey::FrameAdvance | Hotkey::SpeedUp | Hotkey::SlowDown => {
+                    speed.handle_hotkey(hotkey)
+                }
+                Hotkey::SaveState(_)
+                | Hotkey::LoadState(_)
+                | Hotkey::Rewind
+                | Hotkey::Turbo
+                | Hotkey::Screenshot
+                | Hotkey::Debug
+                | Hotkey::ToggleTileViewer
+                | Hotkey::ToggleMapViewer
+                | Hotkey::ToggleSpriteViewer => {}
+            }
+        }
+        speed.set_turbo(input.is_held(Hotkey::Turbo));
+
+        if speed.should_run_frame() {
+            player.run_frame(&mut cpu, &mut mmu);
+            audio.muted = !speed.is_normal_speed();
+            audio.update(audio::CYCLES_PER_FRAME);
+        }
+
+        speed.wait_for_next_frame();
+    }
+
+    audio.stop_recording();
+}
+
+fn set_track_title(graphics: &mut graphics::Graphics, player: &gbs::GbsPlayer) {
+    graphics.set_title(&format!(
+        "{} [{}/{}] - Left/Right to change track",
+        player.gbs.title,
+        player.track + 1,
+        player.gbs.song_count
+    ));
+}
+
+fn toggle_audio_recording(audio: &mut audio::Audio, options: &Options) {
+    if audio.is_recording() {
+        audio.stop_recording();
+        return;
+    }
+
+    // Without --record-audio, name the file after the current time
+    let path = options
+        .record_audio
+        .clone()
+        .unwrap_or_else(|| timestamped_path("wav"));
+    if let Err(e) = audio.start_recording(&path, options.record_channels) {
+        println!("Failed to start audio recording to {}: {}", path, e);
+    }
+}
@@ -0,0 +1,147 @@
+// Booting: mapping a boot ROM over the cartridge, or skipping it and
+// starting from the state it would have left
+
+use rustboy::{Config, GameBoy, Model};
+
+mod common;
+use common::{machine_on, rom};
+
+fn booting(model: Model, boot_rom: Vec<u8>) -> GameBoy {
+    let config = Config {
+        model: Some(model),
+        boot_rom: Some(boot_rom),
+    };
+    let mut rom = rom(&[]);
+    rom[0x0000..0x0100].fill(0xCC);
+    rom[0x0200..0x0900].fill(0xCC);
+    GameBoy::new(&rom, config).unwrap()
+}
+
+#[test]
+fn the_boot_rom_runs_until_it_unmaps_itself() {
+    // LD A, 1; LDH (0xFF50), A
+    let mut boot_rom = vec![0; 0x100];
+    boot_rom[0..4].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    let mut gameboy = booting(Model::Dmg, boot_rom);
+
+    assert!(gameboy.mmu.boot_rom_mapped());
+    assert_eq!(gameboy.cpu.registers.pc, 0x0000);
+    assert_eq!(gameboy.mmu.read_byte(0x0000), 0x3E);
+    // The cartridge header still shows through above the boot ROM
+    assert_eq!(gameboy.mmu.read_byte(0x0101), 0xC3);
+
+    gameboy.step().unwrap();
+    gameboy.step().unwrap();
+    assert!(!gameboy.mmu.boot_rom_mapped());
+    assert_eq!(gameboy.mmu.read_byte(0x0000), 0xCC);
+    assert_eq!(gameboy.cpu.registers.pc, 0x0004);
+}
+
+#[test]
+fn a_zero_write_to_ff50_leaves_the_boot_rom_mapped() {
+    let mut gameboy = booting(Model::Dmg, vec![0xBB; 0x100]);
+    gameboy.mmu.write_byte(0xFF50, 0);
+    assert!(gameboy.mmu.boot_rom_mapped());
+    assert_eq!(gameboy.mmu.read_byte(0x0000), 0xBB);
+}
+
+#[test]
+fn cgb_boot_roms_leave_the_header_to_the_cartridge() {
+    let mut gameboy = booting(Model::Cgb, vec![0xBB; 0x900]);
+    assert_eq!(gameboy.mmu.read_byte(0x0000), 0xBB);
+    assert_eq!(gameboy.mmu.read_byte(0x00FF), 0xBB);
+    // 0x100-0x1FF is the cartridge header, not the boot ROM
+    assert_eq!(gameboy.mmu.read_byte(0x0101), 0xC3);
+    assert_eq!(gameboy.mmu.read_byte(0x01FF), 0x00);
+    assert_eq!(gameboy.mmu.read_byte(0x0200), 0xBB);
+    assert_eq!(gameboy.mmu.read_byte(0x08FF), 0xBB);
+    assert_eq!(gameboy.mmu.read_byte(0x0900), 0x00);
+
+    gameboy.mmu.write_byte(0xFF50, 0x11);
+    assert_eq!(gameboy.mmu.read_byte(0x0000), 0xCC);
+    assert_eq!(gameboy.mmu.read_byte(0x0200), 0xCC);
+}
+
+// AF, BC, DE, HL
+fn registers(gameboy: &GameBoy) -> [u16; 4] {
+    let registers = &gameboy.cpu.registers;
+    [
+        registers.af(),
+        registers.bc(),
+        registers.de(),
+        registers.hl(),
+    ]
+}
+
+#[test]
+fn skipping_the_boot_rom_leaves_each_models_registers() {
+    // The test ROM's header checksum is 0, so DMG and MGB leave only Z set
+    let expected = [
+        (Model::Dmg0, [0x0100, 0xFF13, 0x00C1, 0x8403]),
+        (Model::Dmg, [0x0180, 0x0013, 0x00D8, 0x014D]),
+        (Model::Mgb, [0xFF80, 0x0013, 0x00D8, 0x014D]),
+        (Model::Sgb, [0x0100, 0x0014, 0x0000, 0xC060]),
+        (Model::Sgb2, [0xFF00, 0x0014, 0x0000, 0xC060]),
+        // A monochrome game, so in compatibility mode
+        (Model::Cgb, [0x1180, 0x0000, 0x0008, 0x007C]),
+        (Model::Agb, [0x1100, 0x0100, 0x0008, 0x007C]),
+    ];
+    for (model, registers_after) in expected {
+        let gameboy = machine_on(model, &[]);
+        assert_eq!(registers(&gameboy), registers_after, "{:?}", model);
+        assert_eq!(gameboy.cpu.registers.sp, 0xFFFE, "{:?}", model);
+        assert_eq!(gameboy.cpu.registers.pc, 0x0100, "{:?}", model);
+    }
+
+    let mut rom = rom(&[]);
+    rom[0x143] = 0x80;
+    rom[0x14D] = 0x42;
+    for (model, registers_after) in [
+        (Model::Dmg, [0x01B0, 0x0013, 0x00D8, 0x014D]),
+        (Model::Cgb, [0x1180, 0x0000, 0xFF56, 0x000D]),
+        (Model::Agb, [0x1100, 0x0100, 0xFF56, 0x000D]),
+    ] {
+        let config = Config {
+            model: Some(model),
+            ..Config::default()
+        };
+        let gameboy = GameBoy::new(&rom, config).unwrap();
+        assert_eq!(registers(&gameboy), registers_after, "{:?}", model);
+    }
+}
+
+#[test]
+fn skipping_the_boot_rom_leaves_each_models_io() {
+    // DIV, STAT, NR52, SC, KEY1, VBK, SVBK
+    let io = [0xFF04, 0xFF41, 0xFF26, 0xFF02, 0xFF4D, 0xFF4F, 0xFF70];
+    let expected = [
+        (Model::Dmg0, [0x18, 0x81, 0xF1, 0x7E]),
+        (Model::Dmg, [0xAB, 0x85, 0xF1, 0x7E]),
+        (Model::Mgb, [0xAB, 0x85, 0xF1, 0x7E]),
+        (Model::Sgb, [0xAB, 0x85, 0xF0, 0x7E]),
+        (Model::Sgb2, [0xAB, 0x85, 0xF0, 0x7E]),
+        (Model::Cgb, [0xAB, 0x85, 0xF1, 0x7F]),
+        (Model::Agb, [0xAB, 0x85, 0xF1, 0x7F]),
+    ];
+    for (model, io_after) in expected {
+        let gameboy = machine_on(model, &[]);
+        let memory = &gameboy.mmu.memory;
+        let values: Vec<u8> = io[..4].iter().map(|&addr| memory[addr]).collect();
+        assert_eq!(values, io_after, "{:?}", model);
+        assert_eq!(memory[0xFF40], 0x91, "{:?}", model);
+
+        if model.is_cgb() {
+            let values: Vec<u8> = io[4..].iter().map(|&addr| memory[addr]).collect();
+            assert_eq!(values, [0x7E, 0xFE, 0xF8], "{:?}", model);
+        }
+
+        // Only the monochrome boot ROMs leave the logo in the tile map
+        let logo = memory[0x9904..0x9910].to_vec();
+        let expected_logo: Vec<u8> = if model.is_cgb() {
+            vec![0; 12]
+        } else {
+            (1..=12).collect()
+        };
+        assert_eq!(logo, expected_logo, "{:?}", model);
+    }
+}
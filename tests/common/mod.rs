@@ -0,0 +1,30 @@
+// Helpers shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use rustboy::{Config, GameBoy, Model};
+
+pub mod png;
+
+// A 32 KiB cartridge with no header to speak of. The entry point at 0x0100
+// runs NOP; JP 0x0150, and `program` starts at 0x0150.
+pub fn rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    rom
+}
+
+// A machine running `rom(program)` on the model its header picks, started
+// at 0x0100 as if the boot ROM had run
+pub fn machine(program: &[u8]) -> GameBoy {
+    GameBoy::new(&rom(program), Config::default()).unwrap()
+}
+
+// The same on a particular model
+pub fn machine_on(model: Model, program: &[u8]) -> GameBoy {
+    let config = Config {
+        model: Some(model),
+        ..Config::default()
+    };
+    GameBoy::new(&rom(program), config).unwrap()
+}
@@ -0,0 +1,362 @@
+// A PNG decoder, to check the images the emulator writes. Only the tests
+// read PNGs, so it lives here rather than in the library.
+
+// Decode a non-interlaced PNG to 8-bit RGB, returning (width, height, pixels).
+// Handles every color type at 8 bits per channel, plus grey and palette
+// images packed at 1, 2 or 4 bits per pixel. Alpha is dropped.
+pub fn decode_rgb(data: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err("Not a PNG file".to_string());
+    }
+
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut pos = 8;
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data
+            .get(pos + 8..pos + 8 + len)
+            .ok_or("PNG chunk runs past the end of the file")?;
+        match kind {
+            b"IHDR" if len >= 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + len; // Length, type, data and CRC
+    }
+
+    let header = header.ok_or("PNG has no IHDR chunk")?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    if width == 0 || height == 0 {
+        return Err(format!("PNG has no pixels: {}x{}", width, height));
+    }
+    let (bit_depth, color_type, interlace) = (header[8], header[9], header[12]);
+    if interlace != 0 {
+        return Err("Interlaced PNGs aren't supported".to_string());
+    }
+    let channels = match (color_type, bit_depth) {
+        (0 | 3, 1 | 2 | 4 | 8) => 1,
+        (2, 8) => 3,
+        (4, 8) => 2,
+        (6, 8) => 4,
+        _ => {
+            return Err(format!(
+                "PNG color type {} at {} bits isn't supported",
+                color_type, bit_depth
+            ))
+        }
+    };
+
+    // zlib wraps the deflate stream in a 2-byte header and an Adler-32 trailer
+    if compressed.len() < 6 {
+        return Err("PNG image data is truncated".to_string());
+    }
+    let raw = inflate(&compressed[2..])?;
+
+    // The header's sizes can't be trusted, so check they're even possible
+    let too_big = || format!("PNG is too big: {}x{}", width, height);
+    let bits_per_pixel = channels * bit_depth as usize;
+    let row_bytes = (width as usize)
+        .checked_mul(bits_per_pixel)
+        .ok_or_else(too_big)?
+        .div_ceil(8);
+    let pixel_bytes = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or_else(too_big)?;
+    let rows = unfilter(&raw, row_bytes, height as usize, bits_per_pixel.div_ceil(8))?;
+
+    let mut pixels = Vec::with_capacity(pixel_bytes);
+    for row in rows.chunks(row_bytes) {
+        for x in 0..width as usize {
+            let rgb = match color_type {
+                0 | 3 => {
+                    let bits = bit_depth as usize;
+                    let shift = 8 - bits - (x * bits) % 8;
+                    let value = (row[x * bits / 8] >> shift) & ((1u16 << bits) - 1) as u8;
+                    if color_type == 3 {
+                        let entry = palette
+                            .get(value as usize * 3..value as usize * 3 + 3)
+                            .ok_or("PNG palette index out of range")?;
+                        [entry[0], entry[1], entry[2]]
+                    } else {
+                        // Stretch the grey level to the full 0-255 range
+                        let grey = (value as u32 * 255 / ((1 << bits) - 1)) as u8;
+                        [grey; 3]
+                    }
+                }
+                4 => [row[x * 2]; 3],
+                _ => {
+                    let i = x * channels;
+                    [row[i], row[i + 1], row[i + 2]]
+                }
+            };
+            pixels.extend_from_slice(&rgb);
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+// Undo the per-row filters, returning the rows without their filter bytes
+fn unfilter(raw: &[u8], row_bytes: usize, height: usize, bpp: usize) -> Result<Vec<u8>, String> {
+    let needed = (row_bytes + 1).checked_mul(height);
+    if needed.is_none_or(|needed| raw.len() < needed) {
+        return Err("PNG image data is truncated".to_string());
+    }
+
+    let mut out = vec![0u8; row_bytes * height];
+    for y in 0..height {
+        let filter = raw[y * (row_bytes + 1)];
+        let line = &raw[y * (row_bytes + 1) + 1..(y + 1) * (row_bytes + 1)];
+        for x in 0..row_bytes {
+            let a = if x >= bpp {
+                out[y * row_bytes + x - bpp]
+            } else {
+                0
+            };
+            let b = if y > 0 {
+                out[(y - 1) * row_bytes + x]
+            } else {
+                0
+            };
+            let c = if x >= bpp && y > 0 {
+                out[(y - 1) * row_bytes + x - bpp]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("Unknown PNG filter type {}", filter)),
+            };
+            out[y * row_bytes + x] = line[x].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Base lengths and extra bits for length codes 257-285, and the same for
+// distance codes 0-29
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order the code length code lengths are stored in for dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// Reads a deflate stream least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self
+                .data
+                .get(self.bit / 8)
+                .ok_or("Deflate stream is truncated")?;
+            value |= (((byte >> (self.bit % 8)) & 1) as u32) << i;
+            self.bit += 1;
+        }
+        Ok(value)
+    }
+
+    // Decode one symbol of a canonical Huffman code. Codes are read most
+    // significant bit first, one bit at a time, walking the code lengths.
+    fn symbol(&mut self, huffman: &Huffman) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[len] as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code in deflate stream".to_string())
+    }
+}
+
+// A canonical Huffman code: how many codes there are of each length, and the
+// symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for len in 1..16 {
+            for (symbol, &l) in lengths.iter().enumerate() {
+                if l as usize == len {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Self { counts, symbols }
+    }
+}
+
+// Decompress a raw deflate stream, without zlib's header and trailer
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { data, bit: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                // Stored block, starting at the next byte boundary
+                reader.bit = reader.bit.div_ceil(8) * 8;
+                let len = reader.bits(16)? as usize;
+                reader.bits(16)?; // One's complement of the length
+                let start = reader.bit / 8;
+                let block = data
+                    .get(start..start + len)
+                    .ok_or("Deflate stream is truncated")?;
+                out.extend_from_slice(block);
+                reader.bit += len * 8;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            _ => return Err("Invalid deflate block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &i in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[i] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    // Literal/length and distance code lengths are run-length encoded together
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match reader.symbol(&code_length_code)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or("Deflate repeat with no previous length")?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("Deflate code lengths overrun".to_string());
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = reader.symbol(literals)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err("Invalid deflate length code".to_string());
+                }
+                let len = LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i])? as usize;
+
+                let d = reader.symbol(distances)? as usize;
+                if d >= DISTANCE_BASE.len() {
+                    return Err("Invalid deflate distance code".to_string());
+                }
+                let distance = DISTANCE_BASE[d] as usize + reader.bits(DISTANCE_EXTRA[d])? as usize;
+                if distance > out.len() {
+                    return Err("Deflate distance reaches before the start".to_string());
+                }
+
+                // Copy byte by byte, since the source may overlap what's being written
+                let start = out.len() - distance;
+                for k in 0..len {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// Breakpoints and watchpoints stopping a running machine
+
+use rustboy::audio::CYCLES_PER_FRAME;
+use rustboy::debug::{Breakpoint, Stop, Watchpoint};
+use rustboy::GameBoy;
+
+mod common;
+use common::machine;
+
+// LD A, 0x42; LDH (0x80), A; LD B, 7; INC B; JP 0x0156
+const PROGRAM: [u8; 10] = [0x3E, 0x42, 0xE0, 0x80, 0x06, 0x07, 0x04, 0xC3, 0x56, 0x01];
+
+// Step until something stops the machine, giving up after `limit` steps
+fn run_until_stop(gameboy: &mut GameBoy, limit: usize) -> Option<Stop> {
+    (0..limit).find_map(|_| {
+        gameboy.step().unwrap();
+        gameboy.take_debug_stop()
+    })
+}
+
+#[test]
+fn breakpoint_stops_before_the_instruction() {
+    let mut gameboy = machine(&PROGRAM);
+    gameboy.cpu.breakpoints.list.push(Breakpoint::Opcode(0x04));
+
+    let stop = run_until_stop(&mut gameboy, 10);
+    assert_eq!(stop, Some(Stop::Breakpoint(Breakpoint::Opcode(0x04))));
+    assert_eq!(gameboy.cpu.registers.pc, 0x156);
+    assert_eq!(gameboy.cpu.registers.b, 7);
+
+    // Stays put until resumed, then runs the instruction and stops on the next pass
+    assert_eq!(gameboy.step(), Ok(0));
+    gameboy.take_debug_stop();
+    gameboy.cpu.breakpoints.resume();
+    gameboy.step().unwrap();
+    assert_eq!(gameboy.cpu.registers.b, 8);
+    assert!(run_until_stop(&mut gameboy, 10).is_some());
+    assert_eq!(gameboy.cpu.registers.pc, 0x156);
+}
+
+#[test]
+fn watchpoint_stops_after_the_access() {
+    let mut gameboy = machine(&PROGRAM);
+    gameboy.mmu.watchpoints.list.push(Watchpoint {
+        start: 0xFF80,
+        end: 0xFFFE,
+        on_read: false,
+        on_write: true,
+    });
+
+    let stop = run_until_stop(&mut gameboy, 10);
+    assert_eq!(
+        stop,
+        Some(Stop::Watchpoint {
+            addr: 0xFF80,
+            value: 0x42,
+            write: true
+        })
+    );
+    assert_eq!(gameboy.cpu.registers.pc, 0x154);
+
+    // Reads by the debugger or the rest of the hardware don't count
+    gameboy.mmu.watchpoints.list[0].on_read = true;
+    gameboy.mmu.peek_byte(0xFF80);
+    assert_eq!(gameboy.take_debug_stop(), None);
+}
+
+#[test]
+fn frames_run_a_frame_of_cycles_unless_stopped() {
+    let mut gameboy = machine(&PROGRAM);
+    gameboy.run_frame().unwrap();
+    // The last instruction can run past the end of the frame
+    assert!((CYCLES_PER_FRAME as u64..CYCLES_PER_FRAME as u64 + 24).contains(&gameboy.cycles));
+
+    gameboy.cpu.breakpoints.list.push(Breakpoint::Pc(0x156));
+    let before = gameboy.cycles;
+    gameboy.run_frame().unwrap();
+    assert!(gameboy.cycles - before < 24);
+    assert_eq!(gameboy.cpu.registers.pc, 0x156);
+    assert!(gameboy.take_debug_stop().is_some());
+}
@@ -0,0 +1,96 @@
+// Disassembling instructions, with and without operands
+
+use rustboy::disasm::{disassemble, length};
+use rustboy::EmuError;
+
+mod common;
+use common::machine;
+
+fn text(bytes: &[u8], addr: u16) -> String {
+    disassemble(bytes, addr).text
+}
+
+#[test]
+fn formats_operands() {
+    assert_eq!(text(&[0x00], 0), "NOP");
+    assert_eq!(text(&[0x3E, 0x42], 0), "LD A, $42");
+    assert_eq!(text(&[0x21, 0x00, 0xC0], 0), "LD HL, $C000");
+    assert_eq!(text(&[0xE0, 0x80], 0), "LDH ($FF80), A");
+    assert_eq!(text(&[0xEA, 0x34, 0x12], 0), "LD ($1234), A");
+    assert_eq!(text(&[0x20, 0xFE], 0x150), "JR NZ, $0150");
+    assert_eq!(text(&[0xE8, 0xFE], 0), "ADD SP, -2");
+    assert_eq!(text(&[0xF8, 0x05], 0), "LD HL, SP+5");
+    assert_eq!(text(&[0x76], 0), "HALT");
+    assert_eq!(text(&[0x7E], 0), "LD A, (HL)");
+    assert_eq!(text(&[0xAF], 0), "XOR A");
+    assert_eq!(text(&[0xD3], 0), "DB $D3");
+}
+
+#[test]
+fn decodes_cb_prefixed_opcodes() {
+    assert_eq!(text(&[0xCB, 0x11], 0), "RL C");
+    assert_eq!(text(&[0xCB, 0x37], 0), "SWAP A");
+    assert_eq!(text(&[0xCB, 0x7C], 0), "BIT 7, H");
+    assert_eq!(text(&[0xCB, 0x86], 0), "RES 0, (HL)");
+    assert_eq!(text(&[0xCB, 0xFF], 0), "SET 7, A");
+}
+
+#[test]
+fn lengths_match_operands() {
+    assert_eq!(length(0x00), 1);
+    assert_eq!(length(0x06), 2);
+    assert_eq!(length(0x10), 2);
+    assert_eq!(length(0xC3), 3);
+    assert_eq!(length(0xCB), 2);
+
+    // Missing operand bytes read as 0 but still count
+    let instruction = disassemble(&[0xCD], 0);
+    assert_eq!(instruction.text, "CALL $0000");
+    assert_eq!(instruction.bytes, vec![0xCD, 0x00, 0x00]);
+}
+
+// JR, JP, CALL, RET, RETI and RST, which may put PC anywhere
+fn jumps(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA | 0xE9
+    ) || matches!(opcode, 0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC)
+        || matches!(opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9)
+        || opcode & 0xC7 == 0xC7
+}
+
+#[test]
+fn cpu_moves_past_each_instruction_by_its_length() {
+    for opcode in 0..=0xFF {
+        // Run from WRAM so the instruction and its operands can be placed,
+        // with the stack there too
+        let mut gameboy = machine(&[]);
+        gameboy.cpu.registers.pc = 0xC000;
+        gameboy.cpu.registers.sp = 0xD000;
+        gameboy.mmu.write_byte(0xC000, opcode);
+        let result = gameboy.step();
+
+        let pc = gameboy.cpu.registers.pc;
+        match result {
+            Ok(_) if jumps(opcode) => {}
+            Ok(_) | Err(EmuError::UnimplementedOpcode { .. }) => assert_eq!(
+                pc,
+                0xC000 + length(opcode),
+                "opcode {:02X} left PC at {:04X}",
+                opcode,
+                pc
+            ),
+            Err(_) => {}
+        }
+    }
+
+    // CB-prefixed ones are all two bytes, whether implemented or not
+    for opcode in 0..=0xFF {
+        let mut gameboy = machine(&[]);
+        gameboy.cpu.registers.pc = 0xC000;
+        gameboy.mmu.write_byte(0xC000, 0xCB);
+        gameboy.mmu.write_byte(0xC001, opcode);
+        let _ = gameboy.step();
+        assert_eq!(gameboy.cpu.registers.pc, 0xC002, "opcode CB {:02X}", opcode);
+    }
+}
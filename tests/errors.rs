@@ -0,0 +1,171 @@
+// Errors from running and loading games
+
+use rustboy::audio::CYCLES_PER_FRAME;
+use rustboy::debug::Stop;
+use rustboy::{Config, EmuError, GameBoy, RomLoadError, SaveError};
+
+mod common;
+use common::{machine, rom};
+
+// A machine that has run the prologue and is about to start `program`
+fn at_program(program: &[u8]) -> GameBoy {
+    let mut gameboy = machine(program);
+    gameboy.step().unwrap();
+    gameboy.step().unwrap();
+    gameboy
+}
+
+#[test]
+fn illegal_opcode_locks_up_the_cpu() {
+    // LD A, 1; illegal 0xD3; INC A
+    let mut gameboy = at_program(&[0x3E, 0x01, 0xD3, 0x3C]);
+    gameboy.step().unwrap();
+
+    assert_eq!(
+        gameboy.step(),
+        Err(EmuError::IllegalOpcode {
+            pc: 0x152,
+            opcode: 0xD3
+        })
+    );
+    assert!(gameboy.cpu.locked);
+
+    // Time passes, but nothing more runs
+    for _ in 0..10 {
+        assert_eq!(gameboy.step(), Ok(4));
+    }
+    assert_eq!(gameboy.cpu.registers.pc, 0x152);
+    assert_eq!(gameboy.cpu.registers.a, 1);
+}
+
+#[test]
+fn unimplemented_opcodes_are_skipped() {
+    // LD (0xC000), SP; RLC B; INC A
+    let mut gameboy = at_program(&[0x08, 0x00, 0xC0, 0xCB, 0x00, 0x3C]);
+    let a = gameboy.cpu.registers.a;
+
+    assert_eq!(
+        gameboy.step(),
+        Err(EmuError::UnimplementedOpcode {
+            pc: 0x150,
+            opcode: 0x08
+        })
+    );
+    assert_eq!(
+        gameboy.step(),
+        Err(EmuError::UnimplementedCbOpcode {
+            pc: 0x153,
+            opcode: 0x00
+        })
+    );
+    gameboy.step().unwrap();
+    assert_eq!(gameboy.cpu.registers.a, a.wrapping_add(1));
+    assert!(!gameboy.cpu.locked);
+}
+
+#[test]
+fn frames_finish_despite_errors() {
+    let mut gameboy = at_program(&[0xDD]);
+    assert!(matches!(
+        gameboy.run_frame(),
+        Err(EmuError::IllegalOpcode { opcode: 0xDD, .. })
+    ));
+    assert_eq!(gameboy.run_frame(), Ok(()));
+}
+
+#[test]
+fn failed_instructions_take_their_time() {
+    // LD (0xC000), SP takes 20 cycles even skipped; a lock-up takes 4
+    let mut gameboy = at_program(&[0x08, 0x00, 0xC0, 0xD3]);
+    let cycles = gameboy.cycles;
+    assert!(gameboy.step().is_err());
+    assert_eq!(gameboy.cycles, cycles + 20);
+    assert!(gameboy.step().is_err());
+    assert_eq!(gameboy.cycles, cycles + 24);
+}
+
+#[test]
+fn whole_frames_run_when_continuing_past_errors() {
+    // Unimplemented LD (0xC000), SP over and over
+    let mut gameboy = at_program(&[0x08, 0x00, 0xC0, 0x18, 0xFB]);
+    gameboy.continue_on_error = true;
+    let cycles = gameboy.cycles;
+    assert_eq!(gameboy.run_frame(), Ok(()));
+    assert!(gameboy.cycles >= cycles + CYCLES_PER_FRAME as u64);
+
+    // Locked up, time still passes
+    let mut gameboy = at_program(&[0xDD]);
+    gameboy.continue_on_error = true;
+    let cycles = gameboy.cycles;
+    assert_eq!(gameboy.run_frame(), Ok(()));
+    assert!(gameboy.cycles >= cycles + CYCLES_PER_FRAME as u64);
+}
+
+#[test]
+fn errors_are_debugger_stops() {
+    let error = EmuError::IllegalOpcode {
+        pc: 0x152,
+        opcode: 0xD3,
+    };
+    assert_eq!(
+        Stop::Error(error).to_string(),
+        "Illegal opcode 0xD3 at PC 0x0152, the CPU has locked up"
+    );
+}
+
+#[test]
+fn lock_up_survives_save_states() {
+    let mut gameboy = at_program(&[0xFC]);
+    let _ = gameboy.step();
+    let state = gameboy.save_state();
+
+    let mut restored = at_program(&[0xFC]);
+    restored.load_state(&state).unwrap();
+    assert!(restored.cpu.locked);
+}
+
+#[test]
+fn bad_roms_are_rejected() {
+    assert!(matches!(
+        GameBoy::new(&[0; 0x100], Config::default()),
+        Err(RomLoadError::TooSmall(0x100))
+    ));
+
+    let config = Config {
+        boot_rom: Some(vec![0; 0x200]),
+        ..Config::default()
+    };
+    assert!(matches!(
+        GameBoy::new(&[0; 0x8000], config),
+        Err(RomLoadError::BootRomSize(0x200))
+    ));
+
+    assert!(matches!(
+        rustboy::cartridge::read_rom("/nonexistent/game.gb"),
+        Err(RomLoadError::Read { .. })
+    ));
+}
+
+#[test]
+fn bad_save_states_are_rejected() {
+    let mut gameboy = at_program(&[]);
+    assert!(matches!(
+        gameboy.load_state(b"not a state"),
+        Err(SaveError::Corrupt(_) | SaveError::NotASaveState)
+    ));
+
+    let mut state = gameboy.save_state();
+    state.truncate(state.len() - 1);
+    assert!(matches!(
+        gameboy.load_state(&state),
+        Err(SaveError::Corrupt(_))
+    ));
+
+    let mut other_rom = rom(&[]);
+    other_rom[0x14D] = 0x42;
+    let other = GameBoy::new(&other_rom, Config::default()).unwrap();
+    assert!(matches!(
+        gameboy.load_state(&other.save_state()),
+        Err(SaveError::WrongGame)
+    ));
+}
@@ -0,0 +1,22 @@
+#!/bin/sh
+# Download the test ROM suites into tests/fixtures, laid out the way
+# tests/test_roms.rs expects. Uses the c-sp/game-boy-test-roms bundle, which
+# collects released builds of every suite in one archive.
+set -eu
+
+VERSION=v7.0
+URL="https://github.com/c-sp/game-boy-test-roms/releases/download/$VERSION/game-boy-test-roms-$VERSION.zip"
+
+cd "$(dirname "$0")"
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+echo "Downloading $URL"
+curl -fL -o "$work/roms.zip" "$URL"
+unzip -q "$work/roms.zip" -d "$work/roms"
+
+rm -rf blargg mooneye
+cp -R "$work/roms/blargg" blargg
+cp -R "$work/roms/mooneye-test-suite" mooneye
+
+echo "Test ROMs $VERSION are in $(pwd)"
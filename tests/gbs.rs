@@ -0,0 +1,125 @@
+// Loading GBS music rips
+
+use rustboy::cpu::CPU;
+use rustboy::gbs::{GbsFile, GbsPlayer};
+use rustboy::mmu::MMU;
+use rustboy::model::Model;
+
+// A version 1 header with 3 songs, init and play at the load address, then
+// `code`
+fn gbs(load_address: u16, tma: u8, tac: u8, code: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0; 0x70];
+    bytes[0..3].copy_from_slice(b"GBS");
+    bytes[0x03] = 1; // Version
+    bytes[0x04] = 3; // Songs
+    bytes[0x05] = 1; // First song
+    for offset in [0x06, 0x08, 0x0A] {
+        bytes[offset..offset + 2].copy_from_slice(&load_address.to_le_bytes());
+    }
+    bytes[0x0C..0x0E].copy_from_slice(&0xDFFFu16.to_le_bytes()); // SP
+    bytes[0x0E] = tma;
+    bytes[0x0F] = tac;
+    bytes[0x10..0x15].copy_from_slice(b"Title");
+    bytes.extend_from_slice(code);
+    bytes
+}
+
+#[test]
+fn headers_are_read() {
+    let file = GbsFile::parse(&gbs(0x0400, 0xC0, 0x05, &[0xC9])).unwrap();
+    assert_eq!((file.song_count, file.first_song), (3, 1));
+    assert_eq!(file.load_address, 0x0400);
+    assert_eq!(file.stack_pointer, 0xDFFF);
+    assert_eq!(file.title, "Title");
+    assert_eq!(file.author, "");
+    assert_eq!(file.data, [0xC9]);
+}
+
+#[test]
+fn bad_headers_are_rejected() {
+    let good = gbs(0x0400, 0, 0, &[]);
+    assert!(GbsFile::parse(&good[..0x6F]).is_err());
+
+    let mut magic = good.clone();
+    magic[0..3].copy_from_slice(b"GBX");
+    assert!(GbsFile::parse(&magic).is_err());
+
+    let mut version = good.clone();
+    version[0x03] = 2;
+    assert!(GbsFile::parse(&version).is_err());
+
+    let mut no_songs = good.clone();
+    no_songs[0x04] = 0;
+    assert!(GbsFile::parse(&no_songs).is_err());
+
+    // Below 0x400 the rip would cover the RST and interrupt vectors
+    assert!(GbsFile::parse(&gbs(0x03FF, 0, 0, &[])).is_err());
+    assert!(GbsFile::parse(&gbs(0x8000, 0, 0, &[])).is_err());
+}
+
+#[test]
+fn rst_vectors_jump_into_the_rip() {
+    // Init: JP 0x0018, which lands at load + 0x18: LD A, 0x42; RET
+    let mut code = vec![0; 0x1B];
+    code[0..3].copy_from_slice(&[0xC3, 0x18, 0x00]);
+    code[0x18..0x1B].copy_from_slice(&[0x3E, 0x42, 0xC9]);
+    let file = GbsFile::parse(&gbs(0x0400, 0x12, 0x04, &code)).unwrap();
+
+    let mut cpu = CPU::new(Model::Dmg);
+    let mut mmu = MMU::new(Model::Dmg);
+    let mut player = GbsPlayer::new(file);
+    player.start_track(&mut cpu, &mut mmu, 0);
+
+    for vector in (0x00..0x40).step_by(8) {
+        let target = 0x0400 + vector;
+        let bytes: Vec<u8> = (0..3).map(|i| mmu.read_byte(vector + i)).collect();
+        assert_eq!(bytes, [0xC3, target as u8, (target >> 8) as u8]);
+    }
+    assert_eq!(cpu.registers.a, 0x42);
+    assert_eq!(
+        (mmu.read_byte(0xFF06), mmu.read_byte(0xFF07) & 0x07),
+        (0x12, 0x04)
+    );
+}
+
+#[test]
+fn play_rate_follows_the_timer() {
+    let rate = |tma, tac| {
+        GbsFile::parse(&gbs(0x0400, tma, tac, &[]))
+            .unwrap()
+            .play_rate()
+    };
+
+    // Without the timer enabled the play routine runs every VBlank
+    assert!((rate(0x00, 0x00) - 59.73).abs() < 0.01);
+    assert_eq!(rate(0x00, 0x04), 4096.0 / 256.0);
+    assert_eq!(rate(0xC0, 0x05), 262144.0 / 64.0);
+    assert_eq!(rate(0xFF, 0x06), 65536.0);
+    // Bit 7 asks for CGB double speed
+    assert_eq!(rate(0x00, 0x87), 2.0 * 16384.0 / 256.0);
+}
+
+#[test]
+fn a_failing_routine_stops_the_track() {
+    // Init: RET. Play: INC A, then unimplemented LD (0xC000), SP
+    let mut bytes = gbs(0x0400, 0, 0, &[0xC9, 0x3C, 0x08, 0x00, 0xC0, 0xC9]);
+    bytes[0x0A..0x0C].copy_from_slice(&0x0401u16.to_le_bytes());
+    let file = GbsFile::parse(&bytes).unwrap();
+
+    let mut cpu = CPU::new(Model::Dmg);
+    let mut mmu = MMU::new(Model::Dmg);
+    let mut player = GbsPlayer::new(file);
+    player.start_track(&mut cpu, &mut mmu, 0);
+    let a = cpu.registers.a;
+
+    // The play routine is called once, then not again after it fails
+    for _ in 0..10 {
+        player.run_frame(&mut cpu, &mut mmu);
+    }
+    assert_eq!(cpu.registers.a, a.wrapping_add(1));
+
+    // Starting a track again plays it again
+    player.start_track(&mut cpu, &mut mmu, 0);
+    player.run_frame(&mut cpu, &mut mmu);
+    assert_eq!(cpu.registers.a, 1);
+}
@@ -0,0 +1,133 @@
+// Talks to the GDB stub over a loopback connection
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use rustboy::gdb::{GdbResume, GdbStub};
+
+mod common;
+use common::machine;
+
+// LD A, 0x42; LDH (0x80), A; LD B, 7; INC B; JP 0x0156
+const PROGRAM: [u8; 10] = [0x3E, 0x42, 0xE0, 0x80, 0x06, 0x07, 0x04, 0xC3, 0x56, 0x01];
+
+// The client end of the connection, playing GDB
+struct Client(TcpStream);
+
+impl Client {
+    // Send a packet and return the reply, acknowledging both ways
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.reply()
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.0, "${}#{:02x}", data, checksum).unwrap();
+        assert_eq!(self.byte(), b'+');
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        self.byte();
+        self.byte();
+        self.0.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0u8];
+        self.0.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+#[test]
+fn serves_registers_memory_breakpoints_and_steps() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut gdb = Client(stream);
+        assert_eq!(gdb.request("?"), "S05");
+        assert!(gdb
+            .request("qSupported:swbreak+")
+            .starts_with("PacketSize="));
+        assert_eq!(gdb.request("vMustReplyEmpty"), "");
+
+        // AF, BC, DE, HL, SP, PC after the DMG boot ROM
+        assert_eq!(gdb.request("g"), "80011300d8004d01feff0001");
+        assert_eq!(gdb.request("m150,2"), "3e42");
+
+        assert_eq!(gdb.request("Z0,156,1"), "OK");
+        gdb.send("c");
+        assert_eq!(gdb.reply(), "S05");
+        assert_eq!(gdb.request("p5"), "5601");
+        assert_eq!(gdb.request("mff80,1"), "42");
+
+        assert_eq!(gdb.request("P2=3412"), "OK");
+        assert_eq!(gdb.request("Mc000,2:abcd"), "OK");
+        assert_eq!(gdb.request("mc000,2"), "abcd");
+
+        // Stepping runs the instruction under the breakpoint
+        assert_eq!(gdb.request("s"), "S05");
+        assert_eq!(gdb.request("p5"), "5701");
+        assert_eq!(gdb.request("z0,156,1"), "OK");
+
+        // Watchpoints report the address
+        assert_eq!(gdb.request("Z2,ff80,1"), "OK");
+        assert_eq!(gdb.request("P5=5001"), "OK");
+        gdb.send("c");
+        assert_eq!(gdb.reply(), "T05watch:ff80;");
+
+        // Access watchpoints stop on writes too, as what they are
+        assert_eq!(gdb.request("z2,ff80,1"), "OK");
+        assert_eq!(gdb.request("Z4,ff80,1"), "OK");
+        assert_eq!(gdb.request("P5=5001"), "OK");
+        gdb.send("c");
+        assert_eq!(gdb.reply(), "T05awatch:ff80;");
+        gdb.0.write_all(b"$k#6b").unwrap();
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    let mut stub = GdbStub::new(stream).unwrap();
+    let mut gameboy = machine(&PROGRAM);
+
+    let mut stop = None;
+    let mut resumes = Vec::new();
+    loop {
+        let resume = stub.stop(&mut gameboy, stop);
+        resumes.push(resume);
+        if resume != GdbResume::Continue {
+            break;
+        }
+        stop = loop {
+            gameboy.step().unwrap();
+            if let Some(stop) = gameboy.take_debug_stop() {
+                break Some(stop);
+            }
+        };
+    }
+    client.join().unwrap();
+
+    assert_eq!(
+        resumes,
+        [
+            GdbResume::Continue,
+            GdbResume::Continue,
+            GdbResume::Continue,
+            GdbResume::Kill
+        ]
+    );
+    assert_eq!(gameboy.cpu.registers.de(), 0x1234);
+}
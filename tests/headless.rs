@@ -0,0 +1,98 @@
+// Running the emulator headless from the command line
+
+use std::process::Command;
+
+mod common;
+use common::rom;
+
+// NOP; NOP; JR -2, so PC passes through 0x0151 in the middle of every frame
+// and rests on 0x0152 between them
+const LOOP: [u8; 4] = [0x00, 0x00, 0x18, 0xFC];
+
+// Run a ROM holding `program` headless and return the exit code and output
+fn run(name: &str, program: &[u8], args: &[&str]) -> (i32, String) {
+    run_rom(name, &rom(program), args)
+}
+
+fn run_rom(name: &str, rom: &[u8], args: &[&str]) -> (i32, String) {
+    let dir = std::env::temp_dir().join(format!("rustboy_headless_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.gb", name));
+    std::fs::write(&path, rom).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rustboy"))
+        .arg(&path)
+        .arg("--headless")
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    (output.status.code().unwrap(), stdout)
+}
+
+#[test]
+fn frame_limits_stop_the_run() {
+    let (code, stdout) = run("frames", &LOOP, &["--frames", "3"]);
+    assert_eq!(code, 0);
+    assert!(stdout.contains("Stopped after 3 frames"), "{}", stdout);
+}
+
+#[test]
+fn cycle_limits_stop_mid_frame() {
+    let (code, stdout) = run("cycles", &LOOP, &["--cycles", "100"]);
+    assert_eq!(code, 0);
+    // The instruction running at the limit finishes
+    assert!(stdout.contains("Stopped after 10"), "{}", stdout);
+
+    let (_, stdout) = run("frame_cycles", &LOOP, &["--cycles", "70224"]);
+    assert!(stdout.contains("Stopped after 70224 cycles"), "{}", stdout);
+}
+
+#[test]
+fn exit_conditions_are_checked_every_instruction() {
+    let (code, stdout) = run("pc", &LOOP, &["--exit-on", "pc=0x151", "--frames", "5"]);
+    assert_eq!(code, 0, "{}", stdout);
+    assert!(
+        stdout.contains("Exit condition met after 0 frames"),
+        "{}",
+        stdout
+    );
+
+    // Never reached, so the frame limit ends the run instead
+    let (code, stdout) = run("no_pc", &LOOP, &["--exit-on", "pc=0x200", "--frames", "2"]);
+    assert_eq!(code, 2);
+    assert!(stdout.contains("Stopped after 2 frames"), "{}", stdout);
+}
+
+#[test]
+fn ld_b_b_is_an_exit_condition() {
+    // NOP; NOP; LD B, B; JR -2
+    let program = [0x00, 0x00, 0x40, 0x18, 0xFE];
+    let (code, stdout) = run(
+        "ld_b_b",
+        &program,
+        &["--exit-on", "ld-b-b", "--frames", "5"],
+    );
+    assert_eq!(code, 0, "{}", stdout);
+    assert!(stdout.contains("Exit condition met"), "{}", stdout);
+
+    let (code, _) = run(
+        "no_ld_b_b",
+        &LOOP,
+        &["--exit-on", "ld-b-b", "--frames", "2"],
+    );
+    assert_eq!(code, 2);
+}
+
+#[test]
+fn the_model_option_wins_over_the_header() {
+    let mut cgb_only = rom(&LOOP);
+    cgb_only[0x143] = 0xC0;
+    let (_, stdout) = run_rom("cgb_only", &cgb_only, &["--frames", "1"]);
+    assert!(stdout.contains("Emulating Cgb"), "{}", stdout);
+
+    let args = ["--frames", "1", "--model", "mgb"];
+    let (code, stdout) = run_rom("cgb_only_on_mgb", &cgb_only, &args);
+    assert_eq!(code, 0);
+    assert!(stdout.contains("Emulating Mgb"), "{}", stdout);
+}
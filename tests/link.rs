@@ -0,0 +1,123 @@
+// Links two emulators, over a loopback TCP connection or in one process
+
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use rustboy::audio::CYCLES_PER_FRAME;
+use rustboy::local_link::LinkedPair;
+use rustboy::serial::LinkDevice;
+use rustboy::tcp_link::TcpLink;
+use rustboy::{Config, GameBoy};
+
+mod common;
+use common::rom;
+
+// Connect two links to each other, returning (listening side, connecting side)
+fn linked_pair() -> (TcpLink, TcpLink) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = thread::spawn(move || TcpLink::new(TcpStream::connect(address).unwrap()));
+    let (stream, _) = listener.accept().unwrap();
+    (
+        TcpLink::new(stream).unwrap(),
+        client.join().unwrap().unwrap(),
+    )
+}
+
+// A ROM that puts `sb` in SB, starts a transfer with `sc` and spins
+fn transfer_rom(sb: u8, sc: u8) -> Vec<u8> {
+    rom(&[
+        0x3E, sb, // LD A, sb
+        0xE0, 0x01, // LDH (SB), A
+        0x3E, sc, // LD A, sc
+        0xE0, 0x02, // LDH (SC), A
+        0x18, 0xFE, // JR -2
+    ])
+}
+
+// Run a ROM on a fresh machine plugged into `link` and return SB afterwards
+fn run_linked(link: TcpLink, sb: u8, sc: u8) -> thread::JoinHandle<u8> {
+    thread::spawn(move || {
+        let mut gameboy = GameBoy::new(&transfer_rom(sb, sc), Config::default()).unwrap();
+        gameboy.set_link_device(Box::new(link));
+        for _ in 0..10_000 {
+            gameboy.step().unwrap();
+        }
+        gameboy.mmu.read_byte(0xFF01)
+    })
+}
+
+#[test]
+fn bytes_are_exchanged_between_machines() {
+    let (master, slave) = linked_pair();
+    let master = run_linked(master, 0x42, 0x81); // Internal clock
+    let slave = run_linked(slave, 0x99, 0x80); // External clock
+    assert_eq!(master.join().unwrap(), 0x99);
+    assert_eq!(slave.join().unwrap(), 0x42);
+}
+
+#[test]
+fn a_side_not_transferring_answers_with_its_sb() {
+    let (master, idle) = linked_pair();
+    let master = run_linked(master, 0x42, 0x81);
+    let idle = run_linked(idle, 0x77, 0x00); // Never starts a transfer
+    assert_eq!(master.join().unwrap(), 0x77);
+    assert_eq!(idle.join().unwrap(), 0x77);
+}
+
+#[test]
+fn both_sides_clocking_at_once_read_ff() {
+    let (mut a, mut b) = linked_pair();
+    let b = thread::spawn(move || b.transfer(0x22));
+    assert_eq!(a.transfer(0x11), 0xFF);
+    assert_eq!(b.join().unwrap(), 0xFF);
+}
+
+fn local_pair() -> LinkedPair {
+    let master = GameBoy::new(&transfer_rom(0x42, 0x81), Config::default()).unwrap();
+    let slave = GameBoy::new(&transfer_rom(0x99, 0x80), Config::default()).unwrap();
+    LinkedPair::new(master, slave)
+}
+
+#[test]
+fn bytes_are_exchanged_in_process() {
+    let mut pair = local_pair();
+    for _ in 0..10_000 {
+        pair.step().unwrap();
+    }
+    assert_eq!(pair.machines[0].mmu.read_byte(0xFF01), 0x99);
+    assert_eq!(pair.machines[1].mmu.read_byte(0xFF01), 0x42);
+}
+
+#[test]
+fn in_process_link_is_deterministic() {
+    let run = || {
+        let mut pair = local_pair();
+        for _ in 0..10_000 {
+            pair.step().unwrap();
+        }
+        pair.machines.map(|machine| machine.save_state())
+    };
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn linked_frames_run_when_continuing_past_errors() {
+    // Illegal 0xDD locks the first machine up straight away
+    let locked = GameBoy::new(&rom(&[0xDD]), Config::default()).unwrap();
+    let slave = GameBoy::new(&transfer_rom(0x99, 0x80), Config::default()).unwrap();
+    let mut pair = LinkedPair::new(locked, slave);
+    for machine in &mut pair.machines {
+        machine.continue_on_error = true;
+    }
+    assert_eq!(pair.run_frame(), Ok(()));
+    for machine in &pair.machines {
+        assert!(machine.cycles >= CYCLES_PER_FRAME as u64);
+    }
+}
+
+#[test]
+fn a_transfer_the_other_side_never_answers_reads_ff() {
+    let (mut a, _silent) = linked_pair();
+    assert_eq!(a.transfer(0x11), 0xFF);
+}
@@ -0,0 +1,50 @@
+// Picking the hardware to emulate
+
+use rustboy::cartridge::Header;
+use rustboy::{Config, GameBoy, Model};
+
+mod common;
+use common::rom;
+
+// A header with CGB flag `cgb` at 0x143 and SGB flag `sgb` at 0x146
+fn header(cgb: u8, sgb: u8) -> Header {
+    let mut rom = rom(&[]);
+    rom[0x143] = cgb;
+    rom[0x146] = sgb;
+    Header::parse(&rom)
+}
+
+#[test]
+fn the_cgb_flag_picks_a_game_boy_color() {
+    assert_eq!(Model::from_header(&header(0x00, 0x00)), Model::Dmg);
+    assert_eq!(Model::from_header(&header(0x80, 0x00)), Model::Cgb);
+    assert_eq!(Model::from_header(&header(0xC0, 0x00)), Model::Cgb);
+    // Only bit 7 counts
+    assert_eq!(Model::from_header(&header(0x40, 0x00)), Model::Dmg);
+}
+
+#[test]
+fn the_sgb_flag_doesnt_pick_a_super_game_boy() {
+    // There's no SGB border or palette support, so SGB games run on a DMG,
+    // or a CGB if they support it
+    assert_eq!(Model::from_header(&header(0x00, 0x03)), Model::Dmg);
+    assert_eq!(Model::from_header(&header(0x80, 0x03)), Model::Cgb);
+}
+
+#[test]
+fn a_chosen_model_wins_over_the_header() {
+    let mut rom = rom(&[]);
+    rom[0x143] = 0xC0;
+    assert_eq!(
+        GameBoy::new(&rom, Config::default()).unwrap().model,
+        Model::Cgb
+    );
+
+    let config = Config {
+        model: Some(Model::Dmg),
+        ..Config::default()
+    };
+    let gameboy = GameBoy::new(&rom, config).unwrap();
+    assert_eq!(gameboy.model, Model::Dmg);
+    assert!(!gameboy.mmu.cgb_mode);
+}
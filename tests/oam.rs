@@ -0,0 +1,95 @@
+// Inspecting the sprites in OAM
+
+use rustboy::oam::{LineSprites, Sprite, SpriteSheet};
+use rustboy::vram::TilePalette;
+use rustboy::{GameBoy, Model};
+
+mod common;
+use common::machine_on;
+
+// Put sprite `number` at screen position x,y
+fn place(gameboy: &mut GameBoy, number: u8, x: u8, y: u8, tile: u8, flags: u8) {
+    let start = 0xFE00 + number as usize * 4;
+    gameboy.mmu.memory[start..start + 4].copy_from_slice(&[y + 16, x + 8, tile, flags]);
+}
+
+#[test]
+fn sprites_are_read_from_oam() {
+    let mut gameboy = machine_on(Model::Dmg, &[]);
+    place(&mut gameboy, 5, 40, 32, 0x1C, 0x30);
+
+    let sprite = Sprite::read(&gameboy.mmu, 5);
+    assert_eq!((sprite.x, sprite.y, sprite.tile), (0x30, 0x30, 0x1C));
+    assert!(sprite.x_flip() && !sprite.y_flip() && !sprite.behind_background());
+    assert_eq!(sprite.dmg_palette(), TilePalette::Obp1);
+    assert_eq!(
+        sprite.to_string(),
+        "sprite 05 at 30,30 (screen 40,32) tile 1C flags 30: OBP1, X flip"
+    );
+    assert!(Sprite::read(&gameboy.mmu, 12)
+        .to_string()
+        .starts_with("sprite 0C "));
+    assert_eq!(Sprite::all(&gameboy.mmu).len(), 40);
+}
+
+#[test]
+fn only_ten_sprites_are_drawn_per_line() {
+    let mut gameboy = machine_on(Model::Dmg, &[]);
+    // 12 sprites on lines 20-27, and one below them
+    for number in 0..12 {
+        place(&mut gameboy, number + 3, number * 10, 20, 0, 0);
+    }
+    place(&mut gameboy, 30, 50, 28, 0, 0);
+
+    let line = LineSprites::new(&gameboy.mmu, 27);
+    assert_eq!(line.drawn, (3..13).collect::<Vec<u8>>());
+    assert_eq!(line.dropped, vec![13, 14]);
+    assert_eq!(LineSprites::new(&gameboy.mmu, 28).drawn, vec![30]);
+
+    // Tall sprites reach down into the next one's lines
+    gameboy.mmu.memory[0xFF40] |= 0x04;
+    assert_eq!(LineSprites::new(&gameboy.mmu, 35).dropped, vec![13, 14, 30]);
+}
+
+#[test]
+fn sprites_are_flipped_and_tall_ones_use_tile_pairs() {
+    let mut gameboy = machine_on(Model::Dmg, &[]);
+    // Top left pixel of tile 2 color 3, top left of tile 3 color 1
+    gameboy.mmu.memory[0x8020] = 0x80;
+    gameboy.mmu.memory[0x8021] = 0x80;
+    gameboy.mmu.memory[0x8030] = 0x80;
+    place(&mut gameboy, 0, 0, 0, 0x03, 0x60);
+
+    let rows = Sprite::read(&gameboy.mmu, 0).color_ids(&gameboy.mmu);
+    assert_eq!(rows.len(), 8);
+    assert_eq!(rows[7][7], 1);
+
+    gameboy.mmu.memory[0xFF40] |= 0x04;
+    let rows = Sprite::read(&gameboy.mmu, 0).color_ids(&gameboy.mmu);
+    assert_eq!(rows.len(), 16);
+    // Flipped upside down, tile 3 is on top
+    assert_eq!((rows[7][7], rows[15][7]), (1, 3));
+}
+
+#[test]
+fn sheet_frames_sprites_on_the_chosen_line() {
+    let mut gameboy = machine_on(Model::Dmg, &[]);
+    for number in 0..11 {
+        place(&mut gameboy, number, number * 10, 0, 0, 0);
+    }
+
+    let sheet = SpriteSheet;
+    let pixels = sheet.render(&gameboy.mmu, 4);
+    let border = |number: u8| {
+        let (x, y) = sheet.position(number);
+        let i = ((y * sheet.width() + x) * 3) as usize;
+        [pixels[i], pixels[i + 1], pixels[i + 2]]
+    };
+    assert_eq!(border(0), [0, 192, 0]);
+    assert_eq!(border(10), [255, 0, 0]);
+    assert_eq!(border(11), [32, 32, 32]);
+
+    let (x, y) = sheet.position(13);
+    assert_eq!(sheet.sprite_at(x + 5, y + 5), Some(13));
+    assert_eq!(sheet.sprite_at(sheet.width(), 0), None);
+}
@@ -0,0 +1,106 @@
+// Decoding PNGs and the deflate streams inside them, which the tests use to
+// check the images the emulator writes
+
+use rustboy::png::encode_rgb;
+
+mod common;
+use common::png::{decode_rgb, inflate};
+
+// "Game Boy, Game Boy, Game Boy!" compressed with the fixed Huffman codes
+const FIXED_BLOCK: [u8; 16] = [
+    0x73, 0x4F, 0xCC, 0x4D, 0x55, 0x70, 0xCA, 0xAF, 0xD4, 0x51, 0x70, 0xC7, 0x60, 0x29, 0x02, 0x00,
+];
+
+// `dynamic_text()` compressed with codes of its own
+const DYNAMIC_BLOCK: [u8; 87] = [
+    0xED, 0xD0, 0x2B, 0x0A, 0xC0, 0x30, 0x10, 0x45, 0xD1, 0xAD, 0x64, 0x09, 0xF3, 0xC9, 0xC4, 0x74,
+    0x35, 0x15, 0x11, 0x85, 0x88, 0x8A, 0xD9, 0x3F, 0x69, 0x21, 0xC4, 0x5C, 0x5B, 0x59, 0xF7, 0xB8,
+    0x70, 0xCC, 0xCB, 0x6B, 0xF4, 0x22, 0xE5, 0x3E, 0x47, 0xCF, 0x7C, 0xD6, 0x51, 0xF2, 0x2D, 0xBA,
+    0x8B, 0xAE, 0x62, 0xBB, 0xD8, 0x2A, 0x0E, 0x55, 0xA1, 0x02, 0xAA, 0x41, 0x09, 0x94, 0x42, 0x19,
+    0x94, 0x43, 0x55, 0xA8, 0x80, 0x6A, 0x50, 0x02, 0xA5, 0x50, 0x06, 0xE5, 0x50, 0x15, 0x2A, 0xA0,
+    0x1A, 0xD4, 0xFF, 0xFC, 0xC7, 0xCF, 0x4F,
+];
+
+fn dynamic_text() -> String {
+    (0..40)
+        .map(|i| format!("tile {} palette {}; ", i % 7, i % 3))
+        .collect()
+}
+
+// A PNG chunk with its length and CRC; decoding doesn't check the CRC
+fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&[0; 4]);
+    chunk
+}
+
+#[test]
+fn stored_blocks_are_copied() {
+    // Two stored blocks, the second one last
+    let mut stream = vec![0x00, 0x03, 0x00, 0xFC, 0xFF];
+    stream.extend_from_slice(b"abc");
+    stream.extend_from_slice(&[0x01, 0x02, 0x00, 0xFD, 0xFF]);
+    stream.extend_from_slice(b"de");
+    assert_eq!(inflate(&stream).unwrap(), b"abcde");
+
+    assert!(inflate(&stream[..10]).is_err());
+}
+
+#[test]
+fn fixed_huffman_blocks_are_decoded() {
+    assert_eq!(
+        inflate(&FIXED_BLOCK).unwrap(),
+        b"Game Boy, Game Boy, Game Boy!"
+    );
+    assert!(inflate(&FIXED_BLOCK[..8]).is_err());
+}
+
+#[test]
+fn dynamic_huffman_blocks_are_decoded() {
+    assert_eq!(inflate(&DYNAMIC_BLOCK).unwrap(), dynamic_text().as_bytes());
+}
+
+#[test]
+fn encoded_images_decode_to_the_same_pixels() {
+    let pixels: Vec<u8> = (0..4 * 3 * 3).map(|i| (i * 7) as u8).collect();
+    let png = encode_rgb(4, 3, &pixels);
+    assert_eq!(decode_rgb(&png).unwrap(), (4, 3, pixels));
+}
+
+#[test]
+fn empty_images_are_rejected() {
+    for (width, height) in [(0, 3), (3, 0)] {
+        let mut header = Vec::new();
+        header.extend_from_slice(&u32::to_be_bytes(width));
+        header.extend_from_slice(&u32::to_be_bytes(height));
+        header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunk(b"IHDR", &header));
+        png.extend(chunk(
+            b"IDAT",
+            &[0x78, 0x01, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0, 0, 0, 1],
+        ));
+        png.extend(chunk(b"IEND", &[]));
+        assert!(decode_rgb(&png).is_err());
+    }
+}
+
+#[test]
+fn impossible_sizes_are_rejected() {
+    let mut header = Vec::new();
+    header.extend_from_slice(&u32::MAX.to_be_bytes());
+    header.extend_from_slice(&u32::MAX.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB
+
+    // A zlib header and an empty stored block
+    let data = [0x78, 0x01, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0, 0, 0, 1];
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(chunk(b"IHDR", &header));
+    png.extend(chunk(b"IDAT", &data));
+    png.extend(chunk(b"IEND", &[]));
+    assert!(decode_rgb(&png).is_err());
+}
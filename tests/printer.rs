@@ -0,0 +1,71 @@
+// Drives the Game Boy Printer through its packet protocol
+
+use rustboy::printer::Printer;
+use rustboy::serial::LinkDevice;
+
+mod common;
+use common::png;
+
+// Send one packet, returning the printer's two reply bytes
+fn send(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+    let mut body = vec![command, compressed as u8];
+    body.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    body.extend_from_slice(data);
+    let checksum = body.iter().map(|&b| b as u16).fold(0u16, u16::wrapping_add);
+
+    let mut packet = vec![0x88, 0x33];
+    packet.extend_from_slice(&body);
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    for &byte in &packet {
+        assert_eq!(printer.transfer(byte), 0x00);
+    }
+    (printer.transfer(0x00), printer.transfer(0x00))
+}
+
+#[test]
+fn prints_compressed_band_with_margins() {
+    let dir = std::env::temp_dir().join(format!("rustboy_printer_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("print.png");
+    let mut printer = Printer::new(path.to_str().unwrap());
+
+    assert_eq!(send(&mut printer, 0x01, false, &[]), (0x81, 0x00));
+
+    // One band of tiles whose every line is colour 1 on the left half of
+    // the tile and colour 2 on the right: 0xF0, 0x0F repeated 0x140 times,
+    // sent as five runs of 0x80 literal bytes
+    let mut run = vec![0x7F];
+    for _ in 0..0x40 {
+        run.extend_from_slice(&[0xF0, 0x0F]);
+    }
+    let data = run.repeat(0x280 / 0x80);
+    assert_eq!(send(&mut printer, 0x04, true, &data), (0x81, 0x08));
+
+    // A bad checksum is reported and the packet ignored
+    let bad = [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00];
+    for byte in bad {
+        printer.transfer(byte);
+    }
+    assert_eq!(printer.transfer(0x00), 0x81);
+    assert_eq!(printer.transfer(0x00) & 0x01, 0x01);
+
+    // Print with one margin unit above and below, inverted palette
+    let (alive, status) = send(&mut printer, 0x02, false, &[0x01, 0x11, 0x1B, 0x40]);
+    assert_eq!(alive, 0x81);
+    assert_eq!(status & 0x0B, 0x02); // Busy, nothing left to print, checksum fine
+    printer.tick(u32::MAX);
+    assert_eq!(send(&mut printer, 0x0F, false, &[]), (0x81, 0x00));
+
+    let saved = dir.join("print_1.png");
+    let (width, height, pixels) = png::decode_rgb(&std::fs::read(&saved).unwrap()).unwrap();
+    assert_eq!((width, height), (160, 8 + 16 + 8));
+
+    let pixel = |x: usize, y: usize| pixels[(y * 160 + x) * 3];
+    assert_eq!(pixel(0, 0), 0xFF); // Margin
+    assert_eq!(pixel(0, 8), 0x55); // Colour 1 through palette 0x1B is shade 2
+    assert_eq!(pixel(4, 8), 0xAA); // Colour 2 is shade 1
+    assert_eq!(pixel(159, 23), 0xAA);
+    assert_eq!(pixel(0, 31), 0xFF);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,56 @@
+// Playing a game backwards through its rewind history
+
+use rustboy::rewind::Rewind;
+
+mod common;
+use common::machine;
+
+// INC A; JR -3, so every frame leaves a different state
+const PROGRAM: [u8; 3] = [0x3C, 0x18, 0xFD];
+
+#[test]
+fn stepping_back_restores_each_snapshot_in_turn() {
+    let mut gameboy = machine(&PROGRAM);
+    let mut rewind = Rewind::new(10, 1);
+    let mut states = Vec::new();
+    for _ in 0..5 {
+        gameboy.run_frame().unwrap();
+        rewind.record(&gameboy);
+        states.push(gameboy.save_state());
+    }
+    gameboy.run_frame().unwrap();
+
+    for state in states.iter().rev() {
+        assert!(rewind.step_back(&mut gameboy));
+        assert_eq!(&gameboy.save_state(), state);
+    }
+    assert!(!rewind.step_back(&mut gameboy));
+    assert_eq!(gameboy.save_state(), states[0]);
+}
+
+#[test]
+fn history_is_limited_to_the_seconds_asked_for() {
+    // One second at a snapshot every 30 frames holds 2 snapshots
+    let mut gameboy = machine(&PROGRAM);
+    let mut rewind = Rewind::new(1, 30);
+    let mut states = Vec::new();
+    for _ in 0..4 * 30 {
+        rewind.record(&gameboy);
+        states.push(gameboy.save_state());
+        gameboy.run_frame().unwrap();
+    }
+
+    let mut restored = 0;
+    while rewind.step_back(&mut gameboy) {
+        restored += 1;
+    }
+    // Each snapshot is held for 30 frames
+    assert_eq!(restored, 2 * 30);
+    assert_eq!(gameboy.save_state(), states[2 * 30]);
+}
+
+#[test]
+fn long_histories_do_not_overflow() {
+    assert!(Rewind::new(u32::MAX, 1).enabled());
+    assert!(!Rewind::new(0, 2).enabled());
+}
@@ -0,0 +1,131 @@
+// Saving and restoring the whole machine
+
+use rustboy::savestate::VERSION;
+use rustboy::{Config, GameBoy, Model, SaveError};
+
+mod common;
+use common::{machine, machine_on, rom};
+
+// LD A, 0x42; LD B, 7; INC B; JP 0x0154
+const PROGRAM: [u8; 8] = [0x3E, 0x42, 0x06, 0x07, 0x04, 0xC3, 0x54, 0x01];
+
+fn registers(gameboy: &GameBoy) -> [u16; 6] {
+    let r = &gameboy.cpu.registers;
+    [r.a as u16, r.f as u16, r.b as u16, r.c as u16, r.sp, r.pc]
+}
+
+#[test]
+fn states_restore_registers_and_memory() {
+    let mut gameboy = machine(&PROGRAM);
+    for _ in 0..4 {
+        gameboy.step().unwrap();
+    }
+    for (i, addr) in (0xC000..0xC010).enumerate() {
+        gameboy.mmu.write_byte(addr, i as u8 + 1);
+    }
+    gameboy.mmu.write_byte(0xDFFF, 0x99);
+    let saved = registers(&gameboy);
+    let state = gameboy.save_state();
+
+    gameboy.cpu.registers.a = 0;
+    gameboy.cpu.registers.pc = 0x1234;
+    gameboy.mmu.write_byte(0xC005, 0);
+    gameboy.mmu.write_byte(0xDFFF, 0);
+    gameboy.load_state(&state).unwrap();
+
+    assert_eq!(registers(&gameboy), saved);
+    let r = &gameboy.cpu.registers;
+    assert_eq!((r.a, r.b, r.pc), (0x42, 7, 0x0154));
+    assert_eq!(gameboy.mmu.read_byte(0xC005), 6);
+    assert_eq!(gameboy.mmu.read_byte(0xDFFF), 0x99);
+    assert_eq!(gameboy.save_state(), state);
+}
+
+#[test]
+fn states_restore_cgb_wram_banks() {
+    let mut cgb_rom = rom(&PROGRAM);
+    cgb_rom[0x143] = 0x80; // Supports CGB
+    let config = Config {
+        model: Some(Model::Cgb),
+        ..Config::default()
+    };
+    let mut gameboy = GameBoy::new(&cgb_rom, config).unwrap();
+    gameboy.mmu.write_byte(0xFF70, 3); // SVBK
+    gameboy.mmu.write_byte(0xD000, 0x33);
+    let state = gameboy.save_state();
+
+    gameboy.mmu.write_byte(0xD000, 0);
+    gameboy.mmu.write_byte(0xFF70, 5);
+    gameboy.load_state(&state).unwrap();
+
+    assert_eq!(gameboy.mmu.read_byte(0xFF70) & 0x07, 3);
+    assert_eq!(gameboy.mmu.read_byte(0xD000), 0x33);
+}
+
+#[test]
+fn every_kind_of_bad_state_is_rejected() {
+    let mut gameboy = machine(&PROGRAM);
+    let state = gameboy.save_state();
+
+    assert!(matches!(
+        gameboy.load_state_from_file("/nonexistent/game.ss1"),
+        Err(SaveError::Io { .. })
+    ));
+
+    // The magic follows its 4 byte length, then comes the version
+    let mut wrong_magic = state.clone();
+    wrong_magic[4..8].copy_from_slice(b"NOPE");
+    assert!(matches!(
+        gameboy.load_state(&wrong_magic),
+        Err(SaveError::NotASaveState)
+    ));
+
+    let mut newer = state.clone();
+    newer[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        gameboy.load_state(&newer),
+        Err(SaveError::UnsupportedVersion { found, expected })
+            if found == VERSION + 1 && expected == VERSION
+    ));
+
+    let cgb = machine_on(Model::Cgb, &PROGRAM);
+    assert!(matches!(
+        gameboy.load_state(&cgb.save_state()),
+        Err(SaveError::WrongModel)
+    ));
+
+    let mut other_rom = rom(&PROGRAM);
+    other_rom[0x134] = b'X'; // Title
+    let other = GameBoy::new(&other_rom, Config::default()).unwrap();
+    assert!(matches!(
+        gameboy.load_state(&other.save_state()),
+        Err(SaveError::WrongGame)
+    ));
+
+    let mut trailing = state.clone();
+    trailing.push(0);
+    assert!(matches!(
+        gameboy.load_state(&trailing),
+        Err(SaveError::Corrupt(_))
+    ));
+}
+
+#[test]
+fn truncated_states_are_corrupt_and_change_nothing() {
+    let mut gameboy = machine(&PROGRAM);
+    let state = gameboy.save_state();
+    gameboy.step().unwrap();
+    let before = gameboy.save_state();
+
+    for len in [0, 3, 9, 12, 100, state.len() / 2, state.len() - 1] {
+        assert!(
+            matches!(
+                gameboy.load_state(&state[..len]),
+                Err(SaveError::Corrupt(_))
+            ),
+            "{} bytes",
+            len
+        );
+        assert_eq!(gameboy.save_state(), before);
+    }
+}
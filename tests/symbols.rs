@@ -0,0 +1,41 @@
+// Reading labels from the symbol file formats assemblers write
+
+use rustboy::symbols::Symbols;
+
+#[test]
+fn reads_rgbds_symbols() {
+    let symbols = Symbols::parse(
+        "; File generated by rgblink\n\
+         00:0150 Main\n\
+         00:0150 Main.alias\n\
+         02:4000 FarFunc\n\
+         00:C000 wBuffer\n\
+         01:D000 wBank1\n",
+    );
+    assert_eq!(symbols.len(), 5);
+    assert_eq!(symbols.label(0, 0x0150), Some("Main"));
+    assert_eq!(symbols.address("Main.alias"), Some((0, 0x0150)));
+    assert_eq!(symbols.label(2, 0x4000), Some("FarFunc"));
+    assert_eq!(symbols.label(1, 0x4000), None);
+
+    // RAM labels in bank 0 match whatever bank is mapped
+    assert_eq!(symbols.label(1, 0xC000), Some("wBuffer"));
+    assert_eq!(symbols.label(1, 0xD000), Some("wBank1"));
+}
+
+#[test]
+fn reads_wla_dx_labels_section() {
+    let symbols = Symbols::parse(
+        "[information]\n\
+         version 2\n\
+         [labels]\n\
+         0000:0150 main\n\
+         0001:4000 far_func\n\
+         [definitions]\n\
+         00000010 _sizeof_header\n",
+    );
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols.label(0, 0x0150), Some("main"));
+    assert_eq!(symbols.address("far_func"), Some((1, 0x4000)));
+    assert_eq!(symbols.address("_sizeof_header"), None);
+}
@@ -0,0 +1,215 @@
+// Runs the standard Game Boy test ROM suites headlessly. The ROMs live in
+// tests/fixtures (see the README there); tests/fixtures/fetch.sh downloads
+// them. A suite that hasn't been downloaded is skipped.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rustboy::serial::LinkDevice;
+use rustboy::{Config, GameBoy, Model};
+
+// ROMs known to pass, by file name. Any of these that are present and fail
+// make the suite's test fail, so add ROMs here as they start passing. None
+// do yet: the CPU only implements a few dozen opcodes, and every suite ROM
+// needs more than that before it can report a result. Until one passes the
+// suites can't catch a regression, so they only run when asked for.
+const EXPECTED_PASSES: &[&str] = &[];
+
+// Instructions to run before giving up on a ROM
+const BLARGG_INSTRUCTIONS: u64 = 50_000_000;
+const MOONEYE_INSTRUCTIONS: u64 = 10_000_000;
+
+// LD B,B does nothing, so test ROMs use it as a software breakpoint
+const LD_B_B: u8 = 0x40;
+
+// Blargg's ROMs write their result code to 0xA000, then DE B0 61 to 0xA001
+// to show the result is valid. 0x80 means the test is still running.
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_RUNNING: u8 = 0x80;
+
+// Mooneye's ROMs leave the Fibonacci numbers in B, C, D, E, H and L on success
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+// Keeps everything sent over the link port where the test can read it
+struct SerialCapture(Rc<RefCell<Vec<u8>>>);
+
+impl LinkDevice for SerialCapture {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.0.borrow_mut().push(byte);
+        0xFF
+    }
+}
+
+enum Outcome {
+    Pass,
+    Fail(String),
+}
+
+#[test]
+#[ignore = "no suite ROM passes yet; run with --ignored for the table"]
+fn blargg() {
+    run_suite("blargg", &["gb"], run_blargg);
+}
+
+#[test]
+#[ignore = "no suite ROM passes yet; run with --ignored for the table"]
+fn mooneye() {
+    run_suite("mooneye", &["gb"], run_mooneye);
+}
+
+fn fixtures_dir() -> PathBuf {
+    std::env::var_os("RUSTBOY_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"))
+}
+
+// Run every ROM in a suite, print a summary table and fail if any ROM in
+// EXPECTED_PASSES no longer passes
+fn run_suite(suite: &str, extensions: &[&str], run: fn(&Path) -> Outcome) {
+    let dir = fixtures_dir().join(suite);
+    let mut roms = Vec::new();
+    find_roms(&dir, extensions, &mut roms);
+    if roms.is_empty() {
+        println!(
+            "{}: no ROMs in {}, skipping; run tests/fixtures/fetch.sh to download them",
+            suite,
+            dir.display()
+        );
+        return;
+    }
+    roms.sort();
+
+    let mut regressions = Vec::new();
+    let mut passed = 0;
+    println!("{:<60} Result", suite);
+    for rom in &roms {
+        let name = rom.file_name().unwrap().to_string_lossy().into_owned();
+        let outcome = run(rom);
+        match &outcome {
+            Outcome::Pass => {
+                passed += 1;
+                println!("{:<60} pass", name);
+            }
+            Outcome::Fail(reason) => {
+                println!("{:<60} FAIL: {}", name, reason);
+                if EXPECTED_PASSES.contains(&name.as_str()) {
+                    regressions.push(name);
+                }
+            }
+        }
+    }
+    println!("{}: {}/{} passed", suite, passed, roms.len());
+
+    assert!(
+        regressions.is_empty(),
+        "{}: ROMs that used to pass now fail: {:?}",
+        suite,
+        regressions
+    );
+}
+
+fn find_roms(dir: &Path, extensions: &[&str], roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            find_roms(&path, extensions, roms);
+        } else if path
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+        {
+            roms.push(path);
+        }
+    }
+}
+
+fn load(rom: &Path, model: Option<Model>) -> GameBoy {
+    let data = std::fs::read(rom).expect("Failed to read test ROM");
+    let config = Config {
+        model,
+        ..Config::default()
+    };
+    GameBoy::new(&data, config).expect("Failed to start test ROM")
+}
+
+// Run until the next instruction is LD B,B, returning false on timeout
+fn run_to_breakpoint(gameboy: &mut GameBoy, instructions: u64) -> bool {
+    for _ in 0..instructions {
+        if gameboy.mmu.read_byte(gameboy.cpu.registers.pc) == LD_B_B {
+            return true;
+        }
+        // Unimplemented opcodes are skipped, as the emulator does by
+        // default; the outcome shows whatever they broke
+        let _ = gameboy.step();
+    }
+    false
+}
+
+// Blargg's ROMs print their result over serial and, when they can, also
+// leave it in memory. Either is enough.
+fn run_blargg(rom: &Path) -> Outcome {
+    let mut gameboy = load(rom, None);
+    let output = Rc::new(RefCell::new(Vec::new()));
+    gameboy.set_link_device(Box::new(SerialCapture(output.clone())));
+
+    for i in 0..BLARGG_INSTRUCTIONS {
+        let _ = gameboy.step();
+        if i % 100_000 != 0 {
+            continue;
+        }
+
+        let serial = String::from_utf8_lossy(&output.borrow()).into_owned();
+        if serial.contains("Passed") {
+            return Outcome::Pass;
+        }
+        if serial.contains("Failed") {
+            return Outcome::Fail(serial.trim().replace('\n', " "));
+        }
+
+        let signature = [0xA001, 0xA002, 0xA003].map(|addr| gameboy.mmu.read_byte(addr));
+        let status = gameboy.mmu.read_byte(0xA000);
+        if signature == BLARGG_SIGNATURE && status != BLARGG_RUNNING {
+            return match status {
+                0 => Outcome::Pass,
+                code => Outcome::Fail(format!("result code {}", code)),
+            };
+        }
+    }
+    Outcome::Fail("timed out".to_string())
+}
+
+fn run_mooneye(rom: &Path) -> Outcome {
+    let mut gameboy = load(rom, mooneye_model(rom));
+    if !run_to_breakpoint(&mut gameboy, MOONEYE_INSTRUCTIONS) {
+        return Outcome::Fail("timed out".to_string());
+    }
+
+    let r = &gameboy.cpu.registers;
+    let registers = [r.b, r.c, r.d, r.e, r.h, r.l];
+    if registers == MOONEYE_PASS {
+        Outcome::Pass
+    } else {
+        Outcome::Fail(format!("registers {:02X?}", registers))
+    }
+}
+
+// Mooneye ROM names end in the hardware they target, like boot_regs-dmgABC.gb
+// or boot_regs-sgb2.gb. Anything unrecognized runs on the header's choice.
+fn mooneye_model(rom: &Path) -> Option<Model> {
+    let stem = rom.file_stem()?.to_string_lossy().to_lowercase();
+    let target = stem.rsplit('-').next()?;
+    [
+        ("dmg0", Model::Dmg0),
+        ("dmg", Model::Dmg),
+        ("mgb", Model::Mgb),
+        ("sgb2", Model::Sgb2),
+        ("sgb", Model::Sgb),
+        ("cgb", Model::Cgb),
+        ("agb", Model::Agb),
+    ]
+    .iter()
+    .find(|(prefix, _)| target.starts_with(prefix))
+    .map(|&(_, model)| model)
+}
@@ -0,0 +1,138 @@
+// Instruction traces in the Gameboy Doctor log format
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use rustboy::symbols::Symbols;
+use rustboy::trace::PcRange;
+
+mod common;
+use common::machine;
+
+// A log that can still be read after it's handed to the trace
+#[derive(Clone, Default)]
+struct SharedLog(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedLog {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+// LD A, 0x42; LD B, 7; INC B; JP 0x0154. The header checksum is left at
+// 0, so the boot ROM would leave F at 0x80.
+const PROGRAM: [u8; 8] = [0x3E, 0x42, 0x06, 0x07, 0x04, 0xC3, 0x54, 0x01];
+
+#[test]
+fn trace_lines_match_gameboy_doctor() {
+    let mut gameboy = machine(&PROGRAM);
+    let log = SharedLog::default();
+    gameboy.cpu.trace.log_to(Box::new(log.clone()));
+    for _ in 0..3 {
+        gameboy.step().unwrap();
+    }
+
+    assert_eq!(
+        log.lines(),
+        [
+            "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01",
+            "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,00",
+            "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:3E,42,06,07",
+        ]
+    );
+}
+
+#[test]
+fn trace_filters_by_address_range() {
+    let mut gameboy = machine(&PROGRAM);
+    let log = SharedLog::default();
+    gameboy.cpu.trace.log_to(Box::new(log.clone()));
+    gameboy.cpu.trace.range = Some("0152-0154".parse::<PcRange>().unwrap());
+    for _ in 0..6 {
+        gameboy.step().unwrap();
+    }
+
+    let pcs: Vec<String> = log
+        .lines()
+        .iter()
+        .map(|line| line[48..55].to_string())
+        .collect();
+    assert_eq!(pcs, ["PC:0152", "PC:0154"]);
+
+    // Bank 0 is all there is below 0x4000
+    gameboy.cpu.trace.bank = Some(1);
+    gameboy.step().unwrap();
+    assert_eq!(log.lines().len(), 2);
+}
+
+#[test]
+fn history_keeps_the_last_instructions() {
+    let mut gameboy = machine(&PROGRAM);
+    gameboy.cpu.trace.keep_history(2);
+    for _ in 0..5 {
+        gameboy.step().unwrap();
+    }
+
+    let pcs: Vec<u16> = gameboy
+        .cpu
+        .trace
+        .history()
+        .map(|entry| entry.registers.pc)
+        .collect();
+    assert_eq!(pcs, [0x152, 0x154]);
+}
+
+#[test]
+fn dumps_name_labelled_code_and_targets() {
+    let mut gameboy = machine(&PROGRAM);
+    gameboy.cpu.trace.keep_history(2);
+    for _ in 0..6 {
+        gameboy.step().unwrap();
+    }
+
+    let symbols = Symbols::parse("00:0154 Loop");
+    let mut out = Vec::new();
+    gameboy
+        .cpu
+        .trace
+        .dump_to(&mut out, "Stopped", &symbols)
+        .unwrap();
+    let lines: Vec<String> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "Stopped; the last 2 instructions:");
+    assert_eq!(lines[1], "Loop:");
+    assert!(lines[2].ends_with("PC:0154 PCMEM:04,C3,54,01  00  INC B"));
+    assert!(lines[3].ends_with("PC:0155 PCMEM:C3,54,01,00  00  JP Loop"));
+}
+
+#[test]
+fn pc_ranges_parse_as_hex() {
+    assert_eq!(
+        "0x150-1FF".parse::<PcRange>(),
+        Ok(PcRange {
+            start: 0x150,
+            end: 0x1FF
+        })
+    );
+    assert!("0200-0100".parse::<PcRange>().is_err());
+    assert!("0100".parse::<PcRange>().is_err());
+}
@@ -0,0 +1,179 @@
+// Viewing the tiles in VRAM
+
+use rustboy::vram::{
+    MapAttributes, MapSheet, Tile, TileMap, TilePalette, TileSheet, TILES_PER_BANK,
+};
+use rustboy::Model;
+
+mod common;
+use common::machine_on;
+
+// The RGB value of a pixel of a rendered image `width` pixels across
+fn pixel(width: u32, pixels: &[u8], x: u32, y: u32) -> [u8; 3] {
+    let i = ((y * width + x) * 3) as usize;
+    [pixels[i], pixels[i + 1], pixels[i + 2]]
+}
+
+#[test]
+fn sheet_has_a_column_of_tiles_per_bank() {
+    let dmg = TileSheet::new(&machine_on(Model::Dmg, &[]).mmu);
+    assert_eq!((dmg.width(), dmg.height()), (128, 192));
+
+    let cgb = TileSheet::new(&machine_on(Model::Cgb, &[]).mmu);
+    assert_eq!((cgb.width(), cgb.height()), (256, 192));
+    assert_eq!(
+        cgb.tile_at(130, 9),
+        Some(Tile {
+            bank: 1,
+            number: 0x10
+        })
+    );
+    assert_eq!(cgb.tile_at(256, 0), None);
+}
+
+#[test]
+fn tiles_know_their_address_and_index() {
+    let tile = Tile {
+        bank: 0,
+        number: 0x1A3,
+    };
+    assert_eq!(tile.address(), 0x9A30);
+    assert_eq!(tile.index(), 0xA3);
+    assert_eq!(tile.to_string(), "tile 1A3 index A3 at 0:9A30");
+}
+
+#[test]
+fn tiles_are_drawn_where_they_belong() {
+    let mut gameboy = machine_on(Model::Cgb, &[]);
+    // Top row of tile 0x21 in bank 0 all color 3, and of tile 0 in bank 1 color 1
+    gameboy.mmu.memory[0x8210] = 0xFF;
+    gameboy.mmu.memory[0x8211] = 0xFF;
+    gameboy.mmu.vram_bank1[0] = 0xFF;
+
+    let sheet = TileSheet::new(&gameboy.mmu);
+    let colors = TilePalette::Raw.colors(&gameboy.mmu);
+    let pixels = sheet.render(&gameboy.mmu, TilePalette::Raw);
+    let rgb = |id: usize| [colors[id].0, colors[id].1, colors[id].2];
+
+    assert_eq!(
+        sheet.position(Tile {
+            bank: 0,
+            number: 0x21
+        }),
+        (8, 16)
+    );
+    assert_eq!(pixel(sheet.width(), &pixels, 8, 16), rgb(3));
+    assert_eq!(pixel(sheet.width(), &pixels, 15, 16), rgb(3));
+    assert_eq!(pixel(sheet.width(), &pixels, 8, 17), rgb(0));
+    assert_eq!(pixel(sheet.width(), &pixels, 128, 0), rgb(1));
+}
+
+#[test]
+fn palettes_map_color_ids_through_their_register() {
+    let mut gameboy = machine_on(Model::Dmg, &[]);
+    let raw = TilePalette::Raw.colors(&gameboy.mmu);
+
+    gameboy.mmu.memory[0xFF47] = 0x1B; // Reversed: 0 is black, 3 white
+    let bgp = TilePalette::Bgp.colors(&gameboy.mmu);
+    assert_eq!(bgp, [raw[3], raw[2], raw[1], raw[0]]);
+
+    assert_eq!(TilePalette::Obp1.next(), TilePalette::Raw);
+}
+
+#[test]
+fn map_cells_pick_tiles_by_lcdc_addressing() {
+    let mut gameboy = machine_on(Model::Dmg, &[]);
+    gameboy.mmu.memory[0x9C00 + 2 * 32 + 5] = 0x10;
+    gameboy.mmu.memory[0xFF40] = 0x91; // Tiles from 0x8000
+
+    let cell = MapSheet
+        .cell_at(&gameboy.mmu, 256 + 5 * 8 + 3, 2 * 8 + 7)
+        .unwrap();
+    assert_eq!((cell.map, cell.x, cell.y), (TileMap::High, 5, 2));
+    assert_eq!(cell.address(), 0x9C45);
+    assert_eq!(cell.index, 0x10);
+    assert_eq!(cell.attributes, None);
+    assert_eq!(cell.tile(&gameboy.mmu).address(), 0x8100);
+
+    gameboy.mmu.memory[0xFF40] = 0x81; // Signed indexes from 0x9000
+    assert_eq!(cell.tile(&gameboy.mmu).address(), 0x9100);
+    assert_eq!(cell.to_string(), "map 9C00 cell 5,2 at 9C45, index 10");
+    assert_eq!(MapSheet.cell_at(&gameboy.mmu, 512, 0), None);
+}
+
+#[test]
+fn cgb_map_cells_have_attributes() {
+    let mut gameboy = machine_on(Model::Cgb, &[]);
+    gameboy.mmu.cgb_mode = true;
+    // Palette 3 from bank 1, flipped both ways
+    gameboy.mmu.vram_bank1[0x1800] = 0x6B;
+    // Top left pixel of tile 0 in bank 1 color 3
+    gameboy.mmu.vram_bank1[0] = 0x80;
+    gameboy.mmu.vram_bank1[1] = 0x80;
+
+    let cell = TileMap::Low.cell(&gameboy.mmu, 0, 0);
+    assert_eq!(cell.attributes, Some(MapAttributes(0x6B)));
+    assert_eq!(cell.tile(&gameboy.mmu).bank, 1);
+    assert_eq!(
+        cell.to_string(),
+        "map 9800 cell 0,0 at 9800, index 00, palette 3, bank 1, X flip, Y flip"
+    );
+    // The flipped pixel ends up bottom right
+    let ids = cell.color_ids(&gameboy.mmu);
+    assert_eq!((ids[0][0], ids[7][7]), (0, 3));
+}
+
+#[test]
+fn map_viewer_outlines_the_viewport() {
+    let mut gameboy = machine_on(Model::Dmg, &[]);
+    gameboy.mmu.memory[0xFF40] = 0x99; // Background from 0x9C00
+    gameboy.mmu.memory[0xFF43] = 200; // SCX, so the viewport wraps
+    gameboy.mmu.memory[0xFF42] = 16; // SCY
+
+    let pixels = MapSheet.render(&gameboy.mmu, TilePalette::Raw);
+    let width = MapSheet.width();
+    let (r, g, b) = TilePalette::Raw.colors(&gameboy.mmu)[0];
+    let red = [255, 0, 0];
+    assert_eq!(pixel(width, &pixels, 256 + 200, 16), red);
+    assert_eq!(pixel(width, &pixels, 256 + 200, 16 + 143), red);
+    // The right edge wraps around to x 103
+    assert_eq!(pixel(width, &pixels, 256 + 103, 20), red);
+    assert_eq!(pixel(width, &pixels, 256 + 104, 20), [r, g, b]);
+    assert_ne!(pixel(width, &pixels, 200, 16), red);
+}
+
+#[test]
+fn screen_shows_the_background_from_scx_and_scy() {
+    let mut gameboy = machine_on(Model::Mgb, &[]);
+    // Tile 1 all color 3, at map cell 3,2 of 0x9800
+    gameboy.mmu.memory[0x8010..0x8020].fill(0xFF);
+    gameboy.mmu.memory[0x9800 + 2 * 32 + 3] = 1;
+    gameboy.mmu.memory[0xFF43] = 20;
+    gameboy.mmu.memory[0xFF42] = 12;
+    gameboy.finish_frame();
+
+    let screen = gameboy.framebuffer();
+    // Cell 3,2 starts at 24,16 on the map, 4,4 on screen
+    assert_eq!(pixel(160, screen, 4, 4), [0, 0, 0]);
+    assert_eq!(pixel(160, screen, 11, 11), [0, 0, 0]);
+    assert_eq!(pixel(160, screen, 12, 4), [255, 255, 255]);
+    assert_eq!(pixel(160, screen, 3, 4), [255, 255, 255]);
+}
+
+#[test]
+fn tiles_are_read_from_either_bank() {
+    let mut gameboy = machine_on(Model::Cgb, &[]);
+    gameboy.mmu.memory[0x97F0..0x9800].fill(0x11);
+    gameboy.mmu.vram_bank1[0x17F0..0x1800].fill(0x22);
+    assert_eq!(gameboy.mmu.tile_data_in_bank(0, 383), [0x11; 16]);
+    assert_eq!(gameboy.mmu.tile_data_in_bank(1, 383), [0x22; 16]);
+}
+
+#[test]
+#[should_panic(expected = "No tile 384")]
+fn tiles_past_the_tile_data_are_rejected() {
+    // 0x9800 on is the tile maps, not tiles
+    machine_on(Model::Cgb, &[])
+        .mmu
+        .tile_data_in_bank(0, TILES_PER_BANK);
+}
@@ -0,0 +1,55 @@
+// Recording audio to WAV files
+
+use rustboy::audio::{Audio, CYCLES_PER_FRAME, SAMPLE_RATE};
+use rustboy::model::Model;
+use rustboy::wav::WavWriter;
+
+fn temp_path(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("rustboy_wav_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name).to_string_lossy().into_owned()
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn header_sizes_are_patched_on_drop() {
+    let path = temp_path("header.wav");
+    {
+        let mut writer = WavWriter::create(&path, SAMPLE_RATE, 2).unwrap();
+        writer.write_samples(&[1, -1, 2, -2]).unwrap();
+        writer.write_samples(&[3, -3]).unwrap();
+    }
+
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(data.len(), 44 + 12);
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32_at(&data, 4), 36 + 12);
+    assert_eq!(&data[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(&data, 16), 16);
+    assert_eq!(u32_at(&data, 24), SAMPLE_RATE);
+    assert_eq!(&data[36..40], b"data");
+    assert_eq!(u32_at(&data, 40), 12);
+    assert_eq!(&data[44..46], &1i16.to_le_bytes());
+}
+
+#[test]
+fn recordings_hold_every_frame_generated() {
+    let path = temp_path("frames.wav");
+    let mut audio = Audio::new(Model::Dmg);
+    audio.start_recording(&path, true).unwrap();
+    let mut samples = 0;
+    for _ in 0..3 {
+        audio.update(CYCLES_PER_FRAME);
+        samples += audio.samples().len();
+    }
+    audio.stop_recording();
+
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(u32_at(&data, 40) as usize, samples * 2);
+    // Each channel is mono, so has half as many samples
+    let channel = std::fs::read(temp_path("frames_ch1.wav")).unwrap();
+    assert_eq!(u32_at(&channel, 40) as usize, samples);
+}